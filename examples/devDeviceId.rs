@@ -1,5 +1,46 @@
 /// A sample CLI program to retrieve or generate the device id
-use deviceid::{DevDeviceId, Result};
+use deviceid::{DevDeviceId, Error};
+use std::process::exit;
+
+/// Exit codes, so that automation can tell failures apart; listed in `--help`.
+const EXIT_NO_ID: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_STORAGE: i32 = 3;
+const EXIT_CORRUPT: i32 = 4;
+const EXIT_ALREADY_SET: i32 = 5;
+const EXIT_INVALID_CONFIGURATION: i32 = 6;
+const EXIT_TIMEOUT: i32 = 7;
+const EXIT_READ_ONLY: i32 = 8;
+const EXIT_INVALID_ID: i32 = 9;
+const EXIT_UNSUPPORTED: i32 = 10;
+const EXIT_SEALED: i32 = 11;
+const EXIT_VERIFICATION_FAILED: i32 = 12;
+const EXIT_INVALID_TOKEN: i32 = 13;
+const EXIT_PERMISSION_DENIED: i32 = 14;
+
+const EXIT_CODES: &[(i32, &str)] = &[
+    (0, "Success"),
+    (EXIT_NO_ID, "No Device ID is stored (without -f)"),
+    (EXIT_USAGE, "Unrecognized arguments"),
+    (EXIT_STORAGE, "Storage error"),
+    (EXIT_CORRUPT, "The stored Device ID is corrupt"),
+    (EXIT_ALREADY_SET, "A Device ID is already set"),
+    (EXIT_INVALID_CONFIGURATION, "Invalid configuration"),
+    (EXIT_TIMEOUT, "Storage access timed out"),
+    (EXIT_READ_ONLY, "Storage is read-only"),
+    (EXIT_INVALID_ID, "Invalid Device ID"),
+    (EXIT_UNSUPPORTED, "Storage is unsupported on this platform"),
+    (EXIT_SEALED, "The Device ID is sealed"),
+    (
+        EXIT_VERIFICATION_FAILED,
+        "Storage returned a different Device ID than was stored",
+    ),
+    (EXIT_INVALID_TOKEN, "Invalid Device ID token"),
+    (
+        EXIT_PERMISSION_DENIED,
+        "The storage directory is not writable",
+    ),
+];
 
 enum Command {
     Get,
@@ -7,49 +48,76 @@ enum Command {
     Help,
 }
 
-fn main() -> Result<()> {
+fn exit_code(err: &Error) -> i32 {
+    match err {
+        Error::StorageError(_) => EXIT_STORAGE,
+        Error::BadUuidFormat(_) => EXIT_CORRUPT,
+        Error::AlreadySet => EXIT_ALREADY_SET,
+        Error::InvalidConfiguration(_) => EXIT_INVALID_CONFIGURATION,
+        Error::Timeout(_) => EXIT_TIMEOUT,
+        Error::ReadOnly => EXIT_READ_ONLY,
+        Error::InvalidId(_) => EXIT_INVALID_ID,
+        Error::Unsupported { .. } => EXIT_UNSUPPORTED,
+        Error::Sealed => EXIT_SEALED,
+        Error::VerificationFailed { .. } => EXIT_VERIFICATION_FAILED,
+        Error::InvalidToken(_) => EXIT_INVALID_TOKEN,
+        Error::PermissionDenied { .. } => EXIT_PERMISSION_DENIED,
+    }
+}
+
+/// Reports `err` on stderr and exits with its exit code.
+fn fail(err: Error) -> ! {
+    eprintln!("Error: {err}");
+    exit(exit_code(&err))
+}
+
+fn own_name() -> String {
+    let exe = std::env::current_exe().unwrap();
+    exe.file_name().unwrap().to_string_lossy().into_owned()
+}
+
+fn main() {
     let arg1 = std::env::args().nth(1);
     let cmd = match (std::env::args().count(), arg1.as_deref()) {
         (1, None) => Command::Get,
         (2, Some("-f")) => Command::Generate,
         (2, Some("-h" | "--help" | "-v" | "--version")) => Command::Help,
-        (count, arg1) => {
-            eprintln!("count: {count}, arg1: {arg1:?}");
-            Command::Help
+        _ => {
+            let args: Vec<String> = std::env::args().skip(1).collect();
+            eprintln!(
+                "Unrecognized arguments: {args:?}, see '{} --help'",
+                own_name()
+            );
+            exit(EXIT_USAGE)
         }
     };
 
     match cmd {
-        Command::Get => {
-            let device_id = DevDeviceId::get()?;
-
-            match device_id {
-                Some(id) => println!("Device ID: {}", id),
-                None => {
-                    let exe = std::env::current_exe().unwrap();
-                    let own_name = exe.file_name().unwrap().to_string_lossy();
-                    eprintln!(
-                        "No Device ID found, generate a new one with '{own_name} -f'",
-                        own_name = own_name
-                    );
-                }
+        Command::Get => match DevDeviceId::get() {
+            Ok(Some(id)) => println!("Device ID: {}", id),
+            Ok(None) => {
+                eprintln!(
+                    "No Device ID found, generate a new one with '{own_name} -f'",
+                    own_name = own_name()
+                );
+                exit(EXIT_NO_ID)
             }
-            Ok(())
-        }
-        Command::Generate => {
-            let device_id = DevDeviceId::get_or_generate()?;
-            println!("Device ID: {}", device_id);
-            Ok(())
-        }
+            Err(err) => fail(err),
+        },
+        Command::Generate => match DevDeviceId::get_or_generate() {
+            Ok(device_id) => println!("Device ID: {}", device_id),
+            Err(err) => fail(err),
+        },
         Command::Help => {
-            let exe = std::env::current_exe().unwrap();
-            let own_name = exe.file_name().unwrap().to_string_lossy();
-            println!("Usage: {} [-f] [-h | --help] [-v | --version]", own_name);
+            println!("Usage: {} [-f] [-h | --help] [-v | --version]", own_name());
             println!("Options:");
             println!("  -f               Generate a new Device ID, if one is not already set");
             println!("  -h, --help       Show this help message");
             println!("  -v, --version    Show version information");
-            Ok(())
+            println!("Exit codes:");
+            for (code, meaning) in EXIT_CODES {
+                println!("  {code:<17}{meaning}");
+            }
         }
     }
 }
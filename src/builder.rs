@@ -0,0 +1,1178 @@
+use std::ffi::OsString;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::{
+    BaseDir, Clock, DefaultStorage, DevDeviceId, DotnetInterop, EPHEMERAL_ENV_VAR, Env, Error,
+    FileStorage, GenerationStrategy, IdSource, Metadata, OverwritePolicy, Provenance,
+    ProvisionOutcome, Reconciliation, Result, Scope, Storage, StorageLocation, StoredFormat,
+    SystemClock, counters, ensure_stored, process_env, storage,
+};
+
+/// Configures how the device ID is stored and retrieved.
+///
+/// The default configuration behaves exactly like [`DevDeviceId::get_or_generate`] and [`DevDeviceId::get`].
+///
+/// ```rust
+/// use deviceid::{DevDeviceId, StoredFormat};
+/// let device_id = DevDeviceId::builder()
+///     .stored_format(StoredFormat::Hyphenated)
+///     .get_or_generate()
+///     .unwrap();
+/// eprintln!("Device ID: {}", device_id);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DevDeviceIdBuilder {
+    pub(crate) stored_format: StoredFormat,
+    pub(crate) record_metadata: bool,
+    pub(crate) durable: bool,
+    overwrite_policy: OverwritePolicy,
+    persist_env_id: bool,
+    pub(crate) app_name: Option<String>,
+    #[cfg_attr(not(target_family = "windows"), allow(dead_code))]
+    pub(crate) io_timeout: Option<Duration>,
+    pub(crate) strict_readonly: bool,
+    generation_strategy: GenerationStrategy,
+    allow_nil: bool,
+    #[cfg_attr(not(target_family = "windows"), allow(dead_code))]
+    pub(crate) registry_path: Option<String>,
+    #[cfg_attr(not(target_family = "windows"), allow(dead_code))]
+    pub(crate) bind_machine_guid: bool,
+    #[cfg_attr(not(target_family = "windows"), allow(dead_code))]
+    pub(crate) scope: Scope,
+    pub(crate) clock: Option<Arc<dyn Clock>>,
+    dotnet_interop: DotnetInterop,
+    #[cfg_attr(not(any(target_family = "unix", target_os = "wasi")), allow(dead_code))]
+    pub(crate) base_dir: Option<BaseDir>,
+}
+
+impl DevDeviceIdBuilder {
+    /// Creates a builder with the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the form written when a newly generated ID is stored. Defaults to [`StoredFormat::Hyphenated`].
+    pub fn stored_format(mut self, format: StoredFormat) -> Self {
+        self.stored_format = format;
+        self
+    }
+
+    /// Records [`Metadata`] (crate version and timestamp) alongside a newly stored ID. Defaults to `false`.
+    ///
+    /// The metadata is best-effort: failing to write it does not fail the store.
+    pub fn record_metadata(mut self, record: bool) -> Self {
+        self.record_metadata = record;
+        self
+    }
+
+    /// Sets the clock timestamping the [`Metadata`] of [`DevDeviceIdBuilder::record_metadata`], e.g. a fixed one
+    /// in tests. Defaults to [`SystemClock`](crate::SystemClock).
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Some(Arc::new(clock));
+        self
+    }
+
+    /// Flushes a newly stored ID to disk before returning, so it survives a power loss. Defaults to `false`.
+    ///
+    /// On Unix this calls `fsync` on the file and its directory; on Windows it flushes the registry key.
+    /// This can add tens of milliseconds to the first store, so it is best reserved for installers
+    /// and provisioning tools.
+    pub fn durable(mut self, durable: bool) -> Self {
+        self.durable = durable;
+        self
+    }
+
+    /// Sets what happens when an ID is already stored while storing another one with [`DevDeviceIdBuilder::set`].
+    /// Defaults to [`OverwritePolicy::Error`].
+    ///
+    /// When [`DevDeviceIdBuilder::get_or_generate`] loses a race to another writer, it returns the ID that writer
+    /// stored, unless the policy is [`OverwritePolicy::Overwrite`].
+    pub fn overwrite_policy(mut self, policy: OverwritePolicy) -> Self {
+        self.overwrite_policy = policy;
+        self
+    }
+
+    /// Sets how a new ID is generated when none is stored. Defaults to [`GenerationStrategy::RandomV4`].
+    ///
+    /// A stored ID is always returned as is, whichever strategy generated it.
+    pub fn generation_strategy(mut self, strategy: GenerationStrategy) -> Self {
+        self.generation_strategy = strategy;
+        self
+    }
+
+    /// Accepts the nil UUID in [`DevDeviceIdBuilder::set`] rather than failing with [`Error::InvalidId`].
+    /// Defaults to `false`, so the placeholder all-zeros ID is never stored by accident.
+    pub fn allow_nil(mut self, allow: bool) -> Self {
+        self.allow_nil = allow;
+        self
+    }
+
+    /// Stores the ID taken from the environment by [`DevDeviceIdBuilder::from_env_or_generate`],
+    /// applying the [`OverwritePolicy`]. Defaults to `false`, leaving storage untouched.
+    pub fn persist_env_id(mut self, persist: bool) -> Self {
+        self.persist_env_id = persist;
+        self
+    }
+
+    /// Keeps a separate device ID for the application `name`, stored in a subdirectory (Unix) or subkey (Windows)
+    /// of the shared location, e.g. `Microsoft/DeveloperTools/<name>/deviceid`.
+    ///
+    /// By default, all applications share a single device ID.
+    pub fn app_name(mut self, name: &str) -> Self {
+        self.app_name = Some(name.to_string());
+        self
+    }
+
+    /// Stores the device ID under the registry key `path`, relative to `HKEY_CURRENT_USER`, instead of
+    /// `SOFTWARE\Microsoft\DeveloperTools`, e.g. to isolate tenants sharing a user profile.
+    /// [`DevDeviceIdBuilder::app_name`] nests under this key.
+    ///
+    /// The path must be relative, with `\`-separated segments and no hive prefix or `.`/`..` segments.
+    /// This only applies on Windows.
+    pub fn registry_path(mut self, path: &str) -> Self {
+        self.registry_path = Some(path.to_string());
+        self
+    }
+
+    /// Guarantees that nothing is ever created, modified or removed in storage. Defaults to `false`.
+    ///
+    /// Reading never creates a directory or registry key in any case: [`DevDeviceIdBuilder::get`] returns `None`
+    /// when no ID is stored. With this set, storing fails with [`Error::ReadOnly`] instead of writing, and
+    /// [`DevDeviceIdBuilder::get_repairing`] returns `None` for a corrupt value but leaves it in place.
+    pub fn strict_readonly(mut self, read_only: bool) -> Self {
+        self.strict_readonly = read_only;
+        self
+    }
+
+    /// Stores the device ID under the XDG base directory `base_dir`, e.g. [`BaseDir::Config`] so that dotfile
+    /// backups include it. Defaults to the cache directory on Linux, the state directory on other Unix
+    /// systems, and `Library/Application Support` on macOS.
+    ///
+    /// The directory is taken from its XDG environment variable if that is an absolute path, otherwise
+    /// from its fallback under `$HOME`. An ID stored under another base directory is not moved.
+    /// This only applies on Unix.
+    pub fn base_dir(mut self, base_dir: BaseDir) -> Self {
+        self.base_dir = Some(base_dir);
+        self
+    }
+
+    /// Stores the device ID for the whole machine, under `HKEY_LOCAL_MACHINE`, with [`Scope::Machine`],
+    /// rather than for the current user. Defaults to [`Scope::User`].
+    ///
+    /// Storing a machine-wide ID usually requires elevation. This only applies on Windows.
+    pub fn scope(mut self, scope: Scope) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    /// Ties the stored device ID to the Windows installation: the installation's `MachineGuid` is recorded
+    /// with the ID, and once it differs, e.g. after the OS was reinstalled with a roaming profile,
+    /// the stored ID is treated as absent so that a new one is generated. Defaults to `false`.
+    ///
+    /// This only applies on Windows.
+    pub fn bind_machine_guid(mut self, bind: bool) -> Self {
+        self.bind_machine_guid = bind;
+        self
+    }
+
+    /// Bounds each registry read and write by `timeout`, failing with [`Error::Timeout`] instead of blocking
+    /// indefinitely, e.g. when a roaming profile server is unreachable. Defaults to no timeout.
+    ///
+    /// The access runs on a worker thread, which is left to finish in the background after a timeout.
+    /// This only applies on Windows; file access on other platforms is not bounded.
+    pub fn io_timeout(mut self, timeout: Duration) -> Self {
+        self.io_timeout = Some(timeout);
+        self
+    }
+
+    /// Falls back to the device ID stored by .NET tooling in `~/.microsoft/developer/deviceid` when none is
+    /// stored in the default location, so that both report the same ID. Defaults to [`DotnetInterop::Ignore`].
+    ///
+    /// New IDs are still only stored in the default location, an unreadable .NET tooling ID is ignored,
+    /// and an adopted nil UUID fails with [`Error::InvalidId`] unless [`DevDeviceIdBuilder::allow_nil`] is set.
+    /// With [`DotnetInterop::Copy`], [`DevDeviceIdBuilder::get_or_generate`] stores the adopted ID unless
+    /// [`DevDeviceIdBuilder::strict_readonly`] or [`EPHEMERAL_ENV_VAR`] is set; [`DevDeviceIdBuilder::get`]
+    /// never stores it. This only applies to those two methods on Unix.
+    pub fn dotnet_interop(mut self, interop: DotnetInterop) -> Self {
+        self.dotnet_interop = interop;
+        self
+    }
+
+    /// Resolves the default storage for this platform with this configuration.
+    pub fn storage(&self) -> Result<DefaultStorage> {
+        if let Some(app_name) = &self.app_name {
+            validate_app_name(app_name)?;
+        }
+        if let Some(registry_path) = &self.registry_path {
+            validate_registry_path(registry_path)?;
+        }
+        DefaultStorage::configured(self)
+    }
+
+    /// Retrieves the device ID from storage or generates a new one if it doesn't exist.
+    /// See [`DevDeviceId::get_or_generate`].
+    pub fn get_or_generate(&self) -> Result<DevDeviceId> {
+        let mut storage = self.storage().inspect_err(counters::error)?;
+        self.get_or_generate_with(&process_env, &mut storage, storage::dotnet_id)
+    }
+
+    /// [`DevDeviceIdBuilder::get_or_generate`] with the environment, storage and .NET ID injected.
+    fn get_or_generate_with(
+        &self,
+        env: Env,
+        storage: &mut impl Storage,
+        dotnet_id: impl FnOnce() -> Option<DevDeviceId>,
+    ) -> Result<DevDeviceId> {
+        let adopted = match self.dotnet_interop {
+            DotnetInterop::Ignore => None,
+            DotnetInterop::Adopt | DotnetInterop::Copy => self
+                .retrieve_adopting(storage, dotnet_id, !ephemeral_requested(env))
+                .inspect_err(counters::error)?,
+        };
+        if let Some(id) = adopted {
+            counters::retrieved();
+            return Ok(id);
+        }
+        if ephemeral_requested(env) {
+            return self.get_or_generate_ephemeral_in(storage);
+        }
+        self.get_or_generate_in(storage)
+    }
+
+    /// Retrieves the device ID from storage, returning `None` if it does not exist.
+    /// See [`DevDeviceId::get`].
+    pub fn get(&self) -> Result<Option<DevDeviceId>> {
+        match self.storage() {
+            Ok(mut storage) => self.get_with(&mut storage, storage::dotnet_id),
+            Err(err) => {
+                counters::error(&err);
+                Err(err)
+            }
+        }
+    }
+
+    /// [`DevDeviceIdBuilder::get`] with the storage and .NET ID injected. Nothing is ever stored.
+    fn get_with(
+        &self,
+        storage: &mut impl Storage,
+        dotnet_id: impl FnOnce() -> Option<DevDeviceId>,
+    ) -> Result<Option<DevDeviceId>> {
+        let result = self.retrieve_adopting(storage, dotnet_id, false);
+        match &result {
+            Ok(Some(_)) => counters::retrieved(),
+            Ok(None) => {}
+            Err(err) => counters::error(err),
+        }
+        result
+    }
+
+    /// Like [`DevDeviceIdBuilder::get`], but repairs a corrupt stored value. See [`DevDeviceId::get_repairing`].
+    pub fn get_repairing(&self) -> Result<Option<DevDeviceId>> {
+        DevDeviceId::get_repairing_in(&mut self.storage()?)
+    }
+
+    /// Removes the stored device ID, if any. See [`DevDeviceId::reset`].
+    pub fn reset(&self) -> Result<()> {
+        self.storage()?.clear()
+    }
+
+    /// Uses the device ID from the environment variable `var_name` if it is set, otherwise retrieves or
+    /// generates it from storage. See [`DevDeviceId::from_env_or_generate`].
+    pub fn from_env_or_generate(&self, var_name: &str) -> Result<DevDeviceId> {
+        match env_id(&process_env, var_name)? {
+            // storage is only needed to persist the ID
+            Some(id) if !self.persist_env_id || ephemeral_requested(&process_env) => Ok(id),
+            Some(id) => self.use_env_id(&mut self.storage()?, id),
+            None => self.get_or_generate(),
+        }
+    }
+
+    /// Stores `id` as the device ID, applying the [`OverwritePolicy`] if one is already stored.
+    /// Returns the device ID in storage afterwards. Once `id` is written, reading back a different ID,
+    /// or none, fails with [`Error::VerificationFailed`].
+    pub fn set(&self, id: &DevDeviceId) -> Result<DevDeviceId> {
+        self.storage()
+            .and_then(|mut storage| self.set_in(&mut storage, id))
+            .inspect_err(counters::error)
+    }
+
+    /// Checks that the device ID can be read and stored, without storing one. See [`DevDeviceId::preflight`].
+    pub fn preflight(&self) -> Result<()> {
+        self.storage()?.preflight()
+    }
+
+    /// Watches the stored device ID for changes. See [`DevDeviceId::watch`].
+    #[cfg(feature = "watch")]
+    pub fn watch(&self) -> Result<crate::DeviceIdWatcher> {
+        storage::watch(self.storage()?)
+    }
+
+    /// Seals the stored device ID against overwrite and reset. See [`DevDeviceId::seal`].
+    pub fn seal(&self) -> Result<()> {
+        self.storage()?.seal()
+    }
+
+    /// Undoes [`DevDeviceIdBuilder::seal`]. See [`DevDeviceId::unseal`].
+    pub fn unseal(&self) -> Result<()> {
+        self.storage()?.unseal()
+    }
+
+    /// Reports whether the stored device ID is sealed. See [`DevDeviceId::is_sealed`].
+    pub fn is_sealed(&self) -> Result<bool> {
+        self.storage()?.is_sealed()
+    }
+
+    /// Reports whether the stored device ID equals `expected`. See [`DevDeviceId::verify_matches`].
+    pub fn verify_matches(&self, expected: &DevDeviceId) -> Result<bool> {
+        DevDeviceId::verify_matches_in(&mut self.storage()?, expected)
+    }
+
+    /// Compares the device ID from the environment variable `var_name` to the stored one.
+    /// See [`DevDeviceId::reconcile`].
+    pub fn reconcile(&self, var_name: &str) -> Result<Reconciliation> {
+        self.reconcile_in(var_name, &mut self.storage()?)
+    }
+
+    /// Like [`DevDeviceIdBuilder::reconcile`], but using the given storage backend.
+    pub fn reconcile_in(
+        &self,
+        var_name: &str,
+        storage: &mut impl Storage,
+    ) -> Result<Reconciliation> {
+        self.reconcile_with(var_name, &process_env, storage)
+    }
+
+    /// Like [`DevDeviceIdBuilder::reconcile_in`], but looking up `var_name` with `env` rather than in the
+    /// environment of the process, e.g. a fake environment in tests.
+    pub fn reconcile_with(
+        &self,
+        var_name: &str,
+        env: &dyn Fn(&str) -> Option<OsString>,
+        storage: &mut impl Storage,
+    ) -> Result<Reconciliation> {
+        Ok(Reconciliation::of(
+            env_id(env, var_name)?,
+            storage.retrieve()?,
+        ))
+    }
+
+    /// Retrieves the provenance metadata stored alongside the device ID, if any.
+    /// See [`DevDeviceId::provenance_metadata`].
+    pub fn provenance_metadata(&self) -> Result<Option<Metadata>> {
+        self.storage()?.metadata()
+    }
+
+    /// Like [`DevDeviceIdBuilder::get_or_generate`], but using the file at `path`. See
+    /// [`DevDeviceId::get_or_generate_at`].
+    pub fn get_or_generate_at(&self, path: &Path) -> Result<DevDeviceId> {
+        self.get_or_generate_in(&mut FileStorage::configured_at(self, path))
+    }
+
+    /// Like [`DevDeviceIdBuilder::get`], but using the file at `path`. See [`DevDeviceId::get_at`].
+    pub fn get_at(&self, path: &Path) -> Result<Option<DevDeviceId>> {
+        FileStorage::configured_at(self, path).retrieve()
+    }
+
+    /// Returns the device ID from the first of `sources` that has one. See [`DevDeviceId::resolve_with`].
+    pub fn resolve_with(&self, sources: &[IdSource]) -> Result<Option<DevDeviceId>> {
+        // the default storage is only opened if it is reached
+        self.resolve_with_sources(&process_env, sources, || self.get())
+    }
+
+    /// Like [`DevDeviceIdBuilder::resolve_with`], but [`IdSource::Default`] uses the given storage backend.
+    pub fn resolve_with_in(
+        &self,
+        storage: &mut impl Storage,
+        sources: &[IdSource],
+    ) -> Result<Option<DevDeviceId>> {
+        self.resolve_with_sources(&process_env, sources, || storage.retrieve())
+    }
+
+    fn resolve_with_sources(
+        &self,
+        env: Env,
+        sources: &[IdSource],
+        mut default: impl FnMut() -> Result<Option<DevDeviceId>>,
+    ) -> Result<Option<DevDeviceId>> {
+        for source in sources {
+            let id = match source {
+                IdSource::Env(var_name) => env_id(env, var_name)?,
+                IdSource::File(path) => self.get_at(path)?,
+                IdSource::Default => default()?,
+            };
+            if id.is_some() {
+                return Ok(id);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Like [`DevDeviceIdBuilder::get_or_generate`], but using the given storage backend.
+    ///
+    /// Storage options such as [`DevDeviceIdBuilder::stored_format`] only apply to the default storage,
+    /// so they have no effect here.
+    pub fn get_or_generate_in(&self, storage: &mut impl Storage) -> Result<DevDeviceId> {
+        self.counted_retrieve_or_store_new(storage)
+            .map(|(id, _)| id)
+    }
+
+    /// Retrieves or generates the device ID like [`DevDeviceIdBuilder::get_or_generate`], reporting what
+    /// happened. See [`DevDeviceId::provision`].
+    pub fn provision(&self) -> Result<ProvisionOutcome> {
+        let mut storage = self.storage().inspect_err(counters::error)?;
+        let location = storage::default_location(&storage)?;
+        self.provision_in(&mut storage, location)
+    }
+
+    fn provision_in(
+        &self,
+        storage: &mut impl Storage,
+        location: StorageLocation,
+    ) -> Result<ProvisionOutcome> {
+        let start = Instant::now();
+        let (id, generated) = self.counted_retrieve_or_store_new(storage)?;
+        Ok(ProvisionOutcome {
+            id,
+            generated,
+            location,
+            elapsed: start.elapsed(),
+        })
+    }
+
+    /// Retrieves the ID from `storage`, falling back to `dotnet_id` as [`DevDeviceIdBuilder::dotnet_interop`]
+    /// allows. With [`DotnetInterop::Copy`], the adopted ID is only stored if `may_store` is set.
+    pub(crate) fn retrieve_adopting(
+        &self,
+        storage: &mut impl Storage,
+        dotnet_id: impl FnOnce() -> Option<DevDeviceId>,
+        may_store: bool,
+    ) -> Result<Option<DevDeviceId>> {
+        let stored = storage.retrieve()?;
+        if stored.is_some() || self.dotnet_interop == DotnetInterop::Ignore {
+            return Ok(stored);
+        }
+        let Some(id) = dotnet_id() else {
+            return Ok(None);
+        };
+        self.check_id(&id)?;
+        if self.dotnet_interop == DotnetInterop::Copy && may_store && !self.strict_readonly {
+            return ensure_stored(storage, id).map(Some);
+        }
+        Ok(Some(id))
+    }
+
+    /// Returns the stored ID, or a newly generated one without storing it, for [`EPHEMERAL_ENV_VAR`].
+    fn get_or_generate_ephemeral_in(&self, storage: &mut impl Storage) -> Result<DevDeviceId> {
+        let result = storage.retrieve();
+        match &result {
+            Ok(Some(_)) => counters::retrieved(),
+            Ok(None) => counters::generated(),
+            Err(err) => counters::error(err),
+        }
+        Ok(result?.unwrap_or_else(|| self.generation_strategy.generate(self.now_clock())))
+    }
+
+    fn counted_retrieve_or_store_new(
+        &self,
+        storage: &mut impl Storage,
+    ) -> Result<(DevDeviceId, bool)> {
+        let result = self.retrieve_or_store_new(storage);
+        match &result {
+            Ok((_, true)) => counters::generated(),
+            Ok((_, false)) => counters::retrieved(),
+            Err(err) => counters::error(err),
+        }
+        result
+    }
+
+    /// Returns the stored ID, or stores a newly generated one, along with whether it was newly generated.
+    fn retrieve_or_store_new(&self, storage: &mut impl Storage) -> Result<(DevDeviceId, bool)> {
+        let generate = || self.generation_strategy.generate(self.now_clock());
+        match self.overwrite_policy {
+            OverwritePolicy::Overwrite => {
+                if let Some(id) = storage.retrieve()? {
+                    return Ok((id, false));
+                }
+                let id = generate();
+                Ok((self.set_in(storage, &id)?, true))
+            }
+            OverwritePolicy::Error | OverwritePolicy::KeepExisting => {
+                let (id, provenance) = DevDeviceId::try_resolve(storage, || {
+                    let id = generate();
+                    self.check_id(&id)?;
+                    Ok(id)
+                })?;
+                Ok((id, provenance == Provenance::Generated))
+            }
+        }
+    }
+
+    /// Like [`DevDeviceIdBuilder::from_env_or_generate`], but using the given storage backend.
+    pub fn from_env_or_generate_in(
+        &self,
+        var_name: &str,
+        storage: &mut impl Storage,
+    ) -> Result<DevDeviceId> {
+        self.env_or_generate_with(&process_env, var_name, storage)
+    }
+
+    fn env_or_generate_with(
+        &self,
+        env: Env,
+        var_name: &str,
+        storage: &mut impl Storage,
+    ) -> Result<DevDeviceId> {
+        match env_id(env, var_name)? {
+            Some(id) => self.use_env_id(storage, id),
+            None => self.get_or_generate_in(storage),
+        }
+    }
+
+    fn use_env_id(&self, storage: &mut impl Storage, id: DevDeviceId) -> Result<DevDeviceId> {
+        if self.persist_env_id {
+            self.set_in(storage, &id)?;
+        }
+        Ok(id)
+    }
+
+    /// Like [`DevDeviceIdBuilder::set`], but using the given storage backend.
+    pub fn set_in(&self, storage: &mut impl Storage, id: &DevDeviceId) -> Result<DevDeviceId> {
+        self.check_id(id)?;
+        match self.overwrite_policy {
+            OverwritePolicy::Error => storage.store(id)?,
+            OverwritePolicy::Overwrite => storage.overwrite(id)?,
+            OverwritePolicy::KeepExisting => return ensure_stored(storage, id.clone()),
+        }
+        storage::verify_stored(storage, id.clone())
+    }
+
+    /// The [`DevDeviceIdBuilder::clock`], or the system clock.
+    fn now_clock(&self) -> &dyn Clock {
+        self.clock.as_deref().unwrap_or(&SystemClock)
+    }
+
+    /// Rejects the nil UUID, unless [`DevDeviceIdBuilder::allow_nil`] is set.
+    fn check_id(&self, id: &DevDeviceId) -> Result<()> {
+        if id.is_nil() && !self.allow_nil {
+            return Err(Error::InvalidId(
+                "the nil UUID is not a valid device ID".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Ensures the application name is a single, plain path segment.
+fn validate_app_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || name == "."
+        || name == ".."
+        || name.contains(['/', '\\'])
+        || name.contains(char::is_control)
+    {
+        return Err(Error::InvalidConfiguration(format!(
+            "invalid application name {name:?}"
+        )));
+    }
+    Ok(())
+}
+
+/// Ensures the registry path is a relative subkey path, e.g. `SOFTWARE\Contoso\Tenant1`.
+fn validate_registry_path(path: &str) -> Result<()> {
+    let invalid = || Error::InvalidConfiguration(format!("invalid registry path {path:?}"));
+    let first = path
+        .split('\\')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_uppercase();
+    if first.starts_with("HKEY_")
+        || ["HKCU", "HKLM", "HKCR", "HKU", "HKCC"].contains(&first.as_str())
+    {
+        return Err(invalid());
+    }
+    for segment in path.split('\\') {
+        if segment.is_empty()
+            || segment == "."
+            || segment == ".."
+            || segment.contains(char::is_control)
+        {
+            return Err(invalid());
+        }
+    }
+    Ok(())
+}
+
+/// Whether [`EPHEMERAL_ENV_VAR`] is set to `1`.
+fn ephemeral_requested(env: Env) -> bool {
+    env(EPHEMERAL_ENV_VAR).is_some_and(|value| value == "1")
+}
+
+/// Reads a device ID from the environment variable `var_name`, failing if it is set but not a valid UUID.
+fn env_id(env: Env, var_name: &str) -> Result<Option<DevDeviceId>> {
+    let Some(value) = env(var_name) else {
+        return Ok(None);
+    };
+    let value = value.into_string().map_err(|_| {
+        Error::BadUuidFormat(format!(
+            "{var_name} environment variable is not valid UTF-8"
+        ))
+    })?;
+    let uuid = uuid::Uuid::try_parse(&value)
+        .map_err(|e| Error::BadUuidFormat(format!("{var_name} environment variable: {e}")))?;
+    Ok(Some(DevDeviceId(uuid)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Fixture, MemoryStorage, fake_env};
+
+    fn seeded() -> (MemoryStorage, DevDeviceId) {
+        let existing = DevDeviceId::generate();
+        (MemoryStorage::with_id(existing.clone()), existing)
+    }
+
+    #[test]
+    fn test_overwrite_policy_error() {
+        let (mut storage, existing) = seeded();
+        let builder = DevDeviceIdBuilder::new();
+        let result = builder.set_in(&mut storage, &DevDeviceId::generate());
+        assert!(matches!(result, Err(Error::AlreadySet)));
+        assert_eq!(storage.retrieve().unwrap(), Some(existing));
+    }
+
+    #[test]
+    fn test_overwrite_policy_overwrite() {
+        let (mut storage, _) = seeded();
+        let builder = DevDeviceIdBuilder::new().overwrite_policy(OverwritePolicy::Overwrite);
+        let id = DevDeviceId::generate();
+        assert_eq!(builder.set_in(&mut storage, &id).unwrap(), id);
+        assert_eq!(storage.retrieve().unwrap(), Some(id));
+    }
+
+    #[test]
+    fn test_overwrite_policy_keep_existing() {
+        let (mut storage, existing) = seeded();
+        let builder = DevDeviceIdBuilder::new().overwrite_policy(OverwritePolicy::KeepExisting);
+        let id = DevDeviceId::generate();
+        assert_eq!(builder.set_in(&mut storage, &id).unwrap(), existing);
+        assert_eq!(storage.retrieve().unwrap(), Some(existing));
+    }
+
+    #[test]
+    fn test_overwrite_policy_with_empty_storage() {
+        for policy in [
+            OverwritePolicy::Error,
+            OverwritePolicy::Overwrite,
+            OverwritePolicy::KeepExisting,
+        ] {
+            let mut storage = MemoryStorage::new();
+            let builder = DevDeviceIdBuilder::new().overwrite_policy(policy);
+            let id = DevDeviceId::generate();
+            assert_eq!(builder.set_in(&mut storage, &id).unwrap(), id);
+        }
+    }
+
+    #[test]
+    fn test_get_or_generate_keeps_seeded_id_for_all_policies() {
+        for policy in [
+            OverwritePolicy::Error,
+            OverwritePolicy::Overwrite,
+            OverwritePolicy::KeepExisting,
+        ] {
+            let (mut storage, existing) = seeded();
+            let builder = DevDeviceIdBuilder::new().overwrite_policy(policy);
+            assert_eq!(builder.get_or_generate_in(&mut storage).unwrap(), existing);
+        }
+    }
+
+    /// Simulates losing a race: empty on the first read, then another writer's ID appears before we store.
+    struct RacyStorage {
+        winner: DevDeviceId,
+        reads: usize,
+    }
+
+    impl Storage for RacyStorage {
+        fn retrieve(&mut self) -> Result<Option<DevDeviceId>> {
+            self.reads += 1;
+            Ok((self.reads > 1).then(|| self.winner.clone()))
+        }
+
+        fn store(&mut self, _id: &DevDeviceId) -> Result<()> {
+            Err(Error::AlreadySet)
+        }
+
+        fn overwrite(&mut self, id: &DevDeviceId) -> Result<()> {
+            self.winner = id.clone();
+            Ok(())
+        }
+
+        fn clear(&mut self) -> Result<()> {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn test_get_or_generate_lost_race() {
+        for policy in [OverwritePolicy::Error, OverwritePolicy::KeepExisting] {
+            let winner = DevDeviceId::generate();
+            let mut storage = RacyStorage {
+                winner: winner.clone(),
+                reads: 0,
+            };
+            let builder = DevDeviceIdBuilder::new().overwrite_policy(policy);
+            assert_eq!(builder.get_or_generate_in(&mut storage).unwrap(), winner);
+        }
+    }
+
+    #[test]
+    fn test_provision_reports_generated() {
+        let location = StorageLocation::File("deviceid".into());
+        let builder = DevDeviceIdBuilder::new();
+        let mut storage = MemoryStorage::new();
+        let first = builder
+            .provision_in(&mut storage, location.clone())
+            .unwrap();
+        assert!(first.generated);
+        assert_eq!(first.location, location);
+        assert_eq!(storage.retrieve().unwrap(), Some(first.id.clone()));
+
+        let second = builder.provision_in(&mut storage, location).unwrap();
+        assert!(!second.generated);
+        assert_eq!(second.id, first.id);
+    }
+
+    #[test]
+    fn test_ephemeral_without_stored_id() {
+        let env = fake_env(&[(EPHEMERAL_ENV_VAR, "1")]);
+        let mut storage = MemoryStorage::new();
+        let builder = DevDeviceIdBuilder::new();
+        let get_or_generate = |storage: &mut MemoryStorage| {
+            builder
+                .get_or_generate_with(&env, storage, || None)
+                .unwrap()
+        };
+        let first = get_or_generate(&mut storage);
+        let second = get_or_generate(&mut storage);
+        assert_ne!(first, second);
+        assert_eq!(storage.retrieve().unwrap(), None);
+    }
+
+    #[test]
+    fn test_ephemeral_with_stored_id() {
+        let env = fake_env(&[(EPHEMERAL_ENV_VAR, "1")]);
+        let (mut storage, existing) = seeded();
+        let id = DevDeviceIdBuilder::new()
+            .get_or_generate_with(&env, &mut storage, || None)
+            .unwrap();
+        assert_eq!(id, existing);
+        assert_eq!(storage.retrieve().unwrap(), Some(existing));
+    }
+
+    #[test]
+    fn test_ephemeral_not_requested() {
+        for vars in [
+            &[(EPHEMERAL_ENV_VAR, "0")][..],
+            &[(EPHEMERAL_ENV_VAR, "true")],
+            &[],
+        ] {
+            let env = fake_env(vars);
+            let mut storage = MemoryStorage::new();
+            let id = DevDeviceIdBuilder::new()
+                .get_or_generate_with(&env, &mut storage, || None)
+                .unwrap();
+            assert_eq!(storage.retrieve().unwrap(), Some(id), "{vars:?}");
+        }
+    }
+
+    #[test]
+    fn test_generation_strategy_random_v4() {
+        let mut storage = MemoryStorage::new();
+        let builder = DevDeviceIdBuilder::new().generation_strategy(GenerationStrategy::RandomV4);
+        let id = builder.get_or_generate_in(&mut storage).unwrap();
+        assert_eq!(id.0.get_version_num(), 4);
+        assert_eq!(storage.retrieve().unwrap(), Some(id));
+    }
+
+    #[test]
+    fn test_generation_strategy_sha1_namespace() {
+        let strategy = GenerationStrategy::Sha1Namespace {
+            namespace: uuid::Uuid::NAMESPACE_OID,
+            seed: b"serial-1234".to_vec(),
+        };
+        let builder = DevDeviceIdBuilder::new().generation_strategy(strategy);
+        let mut storage = MemoryStorage::new();
+        let id = builder.get_or_generate_in(&mut storage).unwrap();
+        assert_eq!(id.0.get_version_num(), 5);
+        assert_eq!(
+            id.0,
+            uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, b"serial-1234")
+        );
+        assert_eq!(storage.retrieve().unwrap(), Some(id.clone()));
+        // deterministic: a fresh storage derives the same ID
+        assert_eq!(
+            builder
+                .get_or_generate_in(&mut MemoryStorage::new())
+                .unwrap(),
+            id
+        );
+    }
+
+    #[test]
+    fn test_generation_strategy_custom() {
+        let fixed = DevDeviceId(uuid::Uuid::from_u128(
+            0x550e8400_e29b_41d4_a716_446655440000,
+        ));
+        let generated = fixed.clone();
+        let strategy = GenerationStrategy::Custom(std::sync::Arc::new(move || generated.clone()));
+        let builder = DevDeviceIdBuilder::new().generation_strategy(strategy);
+        let mut storage = MemoryStorage::new();
+        assert_eq!(builder.get_or_generate_in(&mut storage).unwrap(), fixed);
+        assert_eq!(storage.retrieve().unwrap(), Some(fixed));
+    }
+
+    #[test]
+    fn test_generation_strategy_time_ordered_v7() {
+        let clock = crate::fixture::TestClock::new(
+            std::time::UNIX_EPOCH + Duration::from_millis(1_700_000_000_123),
+        );
+        let builder = DevDeviceIdBuilder::new()
+            .generation_strategy(GenerationStrategy::TimeOrderedV7)
+            .clock(clock.clone());
+        let id = builder
+            .get_or_generate_in(&mut MemoryStorage::new())
+            .unwrap();
+        let id2 = builder
+            .get_or_generate_in(&mut MemoryStorage::new())
+            .unwrap();
+        assert_eq!(id.0.get_version_num(), 7);
+        assert_ne!(id, id2);
+        // both share the injected time, to the millisecond
+        let timestamp = id.0.get_timestamp().unwrap().to_unix();
+        assert_eq!(timestamp, (1_700_000_000, 123_000_000));
+        assert_eq!(id2.0.get_timestamp().unwrap().to_unix(), timestamp);
+
+        clock.advance(Duration::from_secs(1));
+        let id3 = builder
+            .get_or_generate_in(&mut MemoryStorage::new())
+            .unwrap();
+        assert_eq!(id3.0.get_timestamp().unwrap().to_unix().0, 1_700_000_001);
+        assert!(id3 > id);
+    }
+
+    #[test]
+    fn test_generation_strategy_ignored_when_stored() {
+        let (mut storage, existing) = seeded();
+        let strategy = GenerationStrategy::Custom(std::sync::Arc::new(|| unreachable!()));
+        let builder = DevDeviceIdBuilder::new().generation_strategy(strategy);
+        assert_eq!(builder.get_or_generate_in(&mut storage).unwrap(), existing);
+    }
+
+    #[test]
+    fn test_set_rejects_nil() {
+        let nil = DevDeviceId(uuid::Uuid::nil());
+        assert!(nil.is_nil());
+        assert!(!DevDeviceId::generate().is_nil());
+        for policy in [
+            OverwritePolicy::Error,
+            OverwritePolicy::Overwrite,
+            OverwritePolicy::KeepExisting,
+        ] {
+            let mut storage = MemoryStorage::new();
+            let builder = DevDeviceIdBuilder::new().overwrite_policy(policy);
+            let result = builder.set_in(&mut storage, &nil);
+            assert!(matches!(result, Err(Error::InvalidId(_))));
+            assert_eq!(storage.retrieve().unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn test_set_allows_nil_with_flag() {
+        let nil = DevDeviceId(uuid::Uuid::nil());
+        let mut storage = MemoryStorage::new();
+        let builder = DevDeviceIdBuilder::new().allow_nil(true);
+        assert_eq!(builder.set_in(&mut storage, &nil).unwrap(), nil);
+        assert_eq!(storage.retrieve().unwrap(), Some(nil));
+    }
+
+    #[test]
+    fn test_invalid_app_names() {
+        for name in ["", ".", "..", "a/b", "a\\b", "../escape", "tab\t"] {
+            let result = DevDeviceIdBuilder::new().app_name(name).storage();
+            assert!(
+                matches!(result, Err(Error::InvalidConfiguration(_))),
+                "{name:?}"
+            );
+        }
+        assert!(validate_app_name("my-app.v2").is_ok());
+    }
+
+    #[test]
+    fn test_invalid_registry_paths() {
+        for path in [
+            "",
+            r"\SOFTWARE\Contoso",
+            r"SOFTWARE\Contoso\",
+            r"SOFTWARE\\Contoso",
+            r"SOFTWARE\..\Contoso",
+            r"HKEY_LOCAL_MACHINE\SOFTWARE\Contoso",
+            r"hkcu\SOFTWARE",
+            "SOFTWARE\tContoso",
+        ] {
+            let result = DevDeviceIdBuilder::new().registry_path(path).storage();
+            assert!(
+                matches!(result, Err(Error::InvalidConfiguration(_))),
+                "{path:?}"
+            );
+        }
+        assert!(validate_registry_path(r"SOFTWARE\Contoso\Tenant 1").is_ok());
+        assert!(validate_registry_path("Contoso").is_ok());
+    }
+
+    #[test]
+    fn test_from_env_set() {
+        let id = DevDeviceId::generate();
+        let env = fake_env(&[("DEVDEVICEID", &id.to_string())]);
+        let mut storage = MemoryStorage::new();
+        let builder = DevDeviceIdBuilder::new();
+        assert_eq!(
+            builder
+                .env_or_generate_with(&env, "DEVDEVICEID", &mut storage)
+                .unwrap(),
+            id
+        );
+        // not persisted unless requested
+        assert_eq!(storage.retrieve().unwrap(), None);
+    }
+
+    #[test]
+    fn test_from_env_set_persisted() {
+        let id = DevDeviceId::generate();
+        let env = fake_env(&[("DEVDEVICEID", &id.to_string())]);
+        let mut storage = MemoryStorage::new();
+        let builder = DevDeviceIdBuilder::new().persist_env_id(true);
+        assert_eq!(
+            builder
+                .env_or_generate_with(&env, "DEVDEVICEID", &mut storage)
+                .unwrap(),
+            id
+        );
+        assert_eq!(storage.retrieve().unwrap(), Some(id));
+    }
+
+    #[test]
+    fn test_from_env_unset() {
+        let env = fake_env(&[]);
+        let (mut storage, existing) = seeded();
+        let builder = DevDeviceIdBuilder::new();
+        assert_eq!(
+            builder
+                .env_or_generate_with(&env, "DEVDEVICEID", &mut storage)
+                .unwrap(),
+            existing
+        );
+
+        let mut storage = MemoryStorage::new();
+        let id = builder
+            .env_or_generate_with(&env, "DEVDEVICEID", &mut storage)
+            .unwrap();
+        assert_eq!(storage.retrieve().unwrap(), Some(id));
+    }
+
+    #[test]
+    fn test_from_env_invalid() {
+        let env = fake_env(&[("DEVDEVICEID", "not-a-uuid")]);
+        let mut storage = MemoryStorage::new();
+        let result =
+            DevDeviceIdBuilder::new().env_or_generate_with(&env, "DEVDEVICEID", &mut storage);
+        assert!(
+            matches!(result, Err(Error::BadUuidFormat(msg)) if msg.contains("DEVDEVICEID environment variable"))
+        );
+        assert_eq!(storage.retrieve().unwrap(), None);
+    }
+
+    #[test]
+    fn test_resolve_with_order() {
+        let fixture = Fixture::new();
+        let file_id = DevDeviceId::generate();
+        DevDeviceIdBuilder::new()
+            .set_in(
+                &mut FileStorage::at(fixture.dir().join("deviceid")),
+                &file_id,
+            )
+            .unwrap();
+        let env_id = DevDeviceId::generate();
+        let env = fake_env(&[("DEVDEVICEID", &env_id.to_string())]);
+        let (mut storage, existing) = seeded();
+        let builder = DevDeviceIdBuilder::new();
+
+        let sources = [
+            IdSource::Env("DEVDEVICEID_UNSET".to_string()),
+            IdSource::File(fixture.dir().join("missing")),
+            IdSource::Env("DEVDEVICEID".to_string()),
+            IdSource::File(fixture.dir().join("deviceid")),
+            IdSource::Default,
+        ];
+        let resolve = |sources: &[IdSource], storage: &mut MemoryStorage| {
+            builder
+                .resolve_with_sources(&env, sources, || storage.retrieve())
+                .unwrap()
+        };
+        assert_eq!(resolve(&sources, &mut storage), Some(env_id));
+        assert_eq!(resolve(&sources[3..], &mut storage), Some(file_id));
+        assert_eq!(resolve(&sources[4..], &mut storage), Some(existing));
+        assert_eq!(resolve(&sources[..2], &mut storage), None);
+        assert_eq!(resolve(&sources[..2], &mut MemoryStorage::new()), None);
+        assert_eq!(resolve(&[], &mut storage), None);
+    }
+
+    #[test]
+    fn test_resolve_with_error_stops_fallthrough() {
+        let fixture = Fixture::new();
+        std::fs::write(fixture.dir().join("deviceid"), "not-a-uuid").unwrap();
+        let env = fake_env(&[("DEVDEVICEID", "not-a-uuid")]);
+        let (mut storage, _) = seeded();
+        let builder = DevDeviceIdBuilder::new();
+
+        let sources = [IdSource::Env("DEVDEVICEID".to_string()), IdSource::Default];
+        let result = builder.resolve_with_sources(&env, &sources, || storage.retrieve());
+        assert!(
+            matches!(result, Err(Error::BadUuidFormat(msg)) if msg.contains("DEVDEVICEID environment variable"))
+        );
+
+        let sources = [
+            IdSource::File(fixture.dir().join("deviceid")),
+            IdSource::Default,
+        ];
+        let result = builder.resolve_with_sources(&env, &sources, || storage.retrieve());
+        assert!(matches!(result, Err(Error::BadUuidFormat(_))));
+    }
+
+    #[test]
+    fn test_dotnet_interop() {
+        let dotnet = DevDeviceId::generate();
+        let dotnet_id = || Some(dotnet.clone());
+
+        let mut storage = MemoryStorage::new();
+        let builder = DevDeviceIdBuilder::new();
+        assert_eq!(
+            builder
+                .retrieve_adopting(&mut storage, dotnet_id, true)
+                .unwrap(),
+            None
+        );
+
+        let builder = DevDeviceIdBuilder::new().dotnet_interop(DotnetInterop::Adopt);
+        let adopted = builder
+            .retrieve_adopting(&mut storage, dotnet_id, true)
+            .unwrap();
+        assert_eq!(adopted, Some(dotnet.clone()));
+        assert_eq!(storage.retrieve().unwrap(), None);
+        assert_eq!(
+            builder
+                .retrieve_adopting(&mut storage, || None, true)
+                .unwrap(),
+            None
+        );
+
+        let read_only = builder
+            .clone()
+            .dotnet_interop(DotnetInterop::Copy)
+            .strict_readonly(true);
+        let adopted = read_only
+            .retrieve_adopting(&mut storage, dotnet_id, true)
+            .unwrap();
+        assert_eq!(adopted, Some(dotnet.clone()));
+        assert_eq!(storage.retrieve().unwrap(), None);
+
+        let builder = DevDeviceIdBuilder::new().dotnet_interop(DotnetInterop::Copy);
+        let adopted = builder
+            .retrieve_adopting(&mut storage, dotnet_id, true)
+            .unwrap();
+        assert_eq!(adopted, Some(dotnet.clone()));
+        assert_eq!(storage.retrieve().unwrap(), Some(dotnet.clone()));
+
+        // a nil ID is rejected as when setting it
+        let nil = || Some(DevDeviceId(uuid::Uuid::nil()));
+        let result = builder.retrieve_adopting(&mut MemoryStorage::new(), nil, true);
+        assert!(matches!(result, Err(Error::InvalidId(_))), "{result:?}");
+        let allow_nil = builder.clone().allow_nil(true);
+        let result = allow_nil.retrieve_adopting(&mut MemoryStorage::new(), nil, true);
+        assert_eq!(result.unwrap(), nil());
+
+        // an ID in the default location wins
+        let (mut storage, existing) = seeded();
+        let adopted = builder
+            .retrieve_adopting(&mut storage, dotnet_id, true)
+            .unwrap();
+        assert_eq!(adopted, Some(existing));
+    }
+
+    #[test]
+    fn test_dotnet_interop_copy_only_from_get_or_generate() {
+        let dotnet = DevDeviceId::generate();
+        let dotnet_id = || Some(dotnet.clone());
+        let builder = DevDeviceIdBuilder::new().dotnet_interop(DotnetInterop::Copy);
+
+        // reading never stores
+        let mut storage = MemoryStorage::new();
+        let adopted = builder.get_with(&mut storage, dotnet_id).unwrap();
+        assert_eq!(adopted, Some(dotnet.clone()));
+        assert_eq!(storage.retrieve().unwrap(), None);
+
+        // nor does an ephemeral run
+        let ephemeral = fake_env(&[(EPHEMERAL_ENV_VAR, "1")]);
+        let id = builder
+            .get_or_generate_with(&ephemeral, &mut storage, dotnet_id)
+            .unwrap();
+        assert_eq!(id, dotnet);
+        assert_eq!(storage.retrieve().unwrap(), None);
+
+        let id = builder
+            .get_or_generate_with(&fake_env(&[]), &mut storage, dotnet_id)
+            .unwrap();
+        assert_eq!(id, dotnet);
+        assert_eq!(storage.retrieve().unwrap(), Some(dotnet.clone()));
+    }
+
+    #[test]
+    fn test_reconcile_with() {
+        const VAR: &str = "DEVDEVICEID";
+        let builder = DevDeviceIdBuilder::new();
+        let (mut storage, existing) = seeded();
+        let other = DevDeviceId::generate();
+
+        let env = fake_env(&[]);
+        let result = builder.reconcile_with(VAR, &env, &mut MemoryStorage::new());
+        assert_eq!(result.unwrap(), Reconciliation::Neither);
+        let result = builder.reconcile_with(VAR, &env, &mut storage);
+        assert_eq!(
+            result.unwrap(),
+            Reconciliation::OnlyStored(existing.clone())
+        );
+
+        let env = fake_env(&[(VAR, &existing.to_string())]);
+        let result = builder.reconcile_with(VAR, &env, &mut storage);
+        assert_eq!(result.unwrap(), Reconciliation::Agree(existing.clone()));
+
+        let env = fake_env(&[(VAR, &other.to_string())]);
+        let result = builder.reconcile_with(VAR, &env, &mut storage);
+        assert_eq!(
+            result.unwrap(),
+            Reconciliation::Differ {
+                env: other.clone(),
+                stored: existing.clone()
+            }
+        );
+        let result = builder.reconcile_with(VAR, &env, &mut MemoryStorage::new());
+        assert_eq!(result.unwrap(), Reconciliation::OnlyEnv(other));
+        // purely informational
+        assert_eq!(storage.retrieve().unwrap(), Some(existing));
+
+        let env = fake_env(&[(VAR, "not-a-uuid")]);
+        let result = builder.reconcile_with(VAR, &env, &mut storage);
+        assert!(matches!(result, Err(Error::BadUuidFormat(_))));
+    }
+}
@@ -0,0 +1,23 @@
+//! Sources of the current time; see [`Clock`].
+
+use std::time::SystemTime;
+
+/// A source of the current time, e.g. for the timestamp of [`Metadata`](crate::Metadata) and of
+/// [`GenerationStrategy::TimeOrderedV7`](crate::GenerationStrategy::TimeOrderedV7) IDs.
+///
+/// Defaults to [`SystemClock`]. Tests can inject a controllable one, such as the `TestClock` of the
+/// `test-util` feature, with [`DevDeviceIdBuilder::clock`](crate::DevDeviceIdBuilder::clock).
+pub trait Clock: std::fmt::Debug + Send + Sync {
+    /// The current time.
+    fn now(&self) -> SystemTime;
+}
+
+/// The system clock, [`SystemTime::now`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
@@ -0,0 +1,128 @@
+//! Operational counters, emitted through the `metrics` facade when the `metrics` feature is enabled:
+//!
+//! - `devdeviceid.generated`: a new device ID was generated and stored
+//! - `devdeviceid.retrieved`: a stored device ID was returned
+//! - `devdeviceid.error`: an operation failed, labelled with the `kind` of [`Error`]
+//!
+//! Without the feature, these are empty functions.
+
+use crate::Error;
+
+#[cfg(feature = "metrics")]
+pub(crate) fn generated() {
+    ::metrics::counter!("devdeviceid.generated").increment(1);
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn retrieved() {
+    ::metrics::counter!("devdeviceid.retrieved").increment(1);
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn error(err: &Error) {
+    ::metrics::counter!("devdeviceid.error", "kind" => kind(err)).increment(1);
+}
+
+#[cfg(not(feature = "metrics"))]
+#[inline(always)]
+pub(crate) fn generated() {}
+
+#[cfg(not(feature = "metrics"))]
+#[inline(always)]
+pub(crate) fn retrieved() {}
+
+#[cfg(not(feature = "metrics"))]
+#[inline(always)]
+pub(crate) fn error(_err: &Error) {}
+
+/// The `kind` label for an error: the variant name in snake case.
+#[cfg(feature = "metrics")]
+fn kind(err: &Error) -> &'static str {
+    match err {
+        Error::StorageError(_) => "storage_error",
+        Error::BadUuidFormat(_) => "bad_uuid_format",
+        Error::AlreadySet => "already_set",
+        Error::InvalidConfiguration(_) => "invalid_configuration",
+        Error::Timeout(_) => "timeout",
+        Error::ReadOnly => "read_only",
+        Error::InvalidId(_) => "invalid_id",
+        Error::Unsupported { .. } => "unsupported",
+        Error::Sealed => "sealed",
+        Error::VerificationFailed { .. } => "verification_failed",
+        Error::PermissionDenied { .. } => "permission_denied",
+        Error::InvalidToken(_) => "invalid_token",
+    }
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::*;
+    use crate::{DevDeviceId, DevDeviceIdBuilder, GenerationStrategy, MemoryStorage};
+    use ::metrics::{
+        Counter, CounterFn, Gauge, Histogram, Key, KeyName, Metadata, Recorder, SharedString, Unit,
+    };
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    type Counts = Arc<Mutex<HashMap<String, u64>>>;
+
+    /// Counts increments per counter name and labels, e.g. `devdeviceid.error{kind=already_set}`.
+    #[derive(Default)]
+    struct CountingRecorder(Counts);
+
+    struct CountingHandle(String, Counts);
+
+    impl CounterFn for CountingHandle {
+        fn increment(&self, value: u64) {
+            *self.1.lock().unwrap().entry(self.0.clone()).or_default() += value;
+        }
+
+        fn absolute(&self, value: u64) {
+            self.1.lock().unwrap().insert(self.0.clone(), value);
+        }
+    }
+
+    impl Recorder for CountingRecorder {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            let mut name = key.name().to_string();
+            for label in key.labels() {
+                name.push_str(&format!("{{{}={}}}", label.key(), label.value()));
+            }
+            Counter::from_arc(Arc::new(CountingHandle(name, self.0.clone())))
+        }
+
+        fn register_gauge(&self, _: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::noop()
+        }
+
+        fn register_histogram(&self, _: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::noop()
+        }
+    }
+
+    #[test]
+    fn test_counters() {
+        let recorder = CountingRecorder::default();
+        let counts = recorder.0.clone();
+        ::metrics::with_local_recorder(&recorder, || {
+            let mut storage = MemoryStorage::new();
+            let builder = DevDeviceIdBuilder::new();
+            builder.get_or_generate_in(&mut storage).unwrap();
+            builder.get_or_generate_in(&mut storage).unwrap();
+            builder.get_or_generate_in(&mut storage).unwrap();
+
+            let nil = GenerationStrategy::Custom(Arc::new(|| DevDeviceId(uuid::Uuid::nil())));
+            let builder = DevDeviceIdBuilder::new().generation_strategy(nil);
+            let result = builder.get_or_generate_in(&mut MemoryStorage::new());
+            assert!(matches!(result, Err(Error::InvalidId(_))));
+        });
+        let counts = counts.lock().unwrap();
+        assert_eq!(counts.get("devdeviceid.generated"), Some(&1));
+        assert_eq!(counts.get("devdeviceid.retrieved"), Some(&2));
+        assert_eq!(counts.get("devdeviceid.error{kind=invalid_id}"), Some(&1));
+    }
+}
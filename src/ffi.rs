@@ -0,0 +1,83 @@
+#![cfg(feature = "uniffi")]
+
+//! UniFFI bindings for mobile apps; see [`DeviceIdService`].
+
+use crate::{DevDeviceId, DevDeviceIdBuilder, FileStorage, Result, Storage};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Retrieves, generates and resets the device ID for apps calling Rust through UniFFI, e.g. on iOS and
+/// Android, with IDs passed as lowercase hyphenated strings.
+///
+/// Mobile sandboxes rarely allow the default location, so the service is usually constructed with the
+/// path of a file in the app's own storage, e.g. `Context.getFilesDir()` on Android or the Application
+/// Support directory on iOS. This crate is built only as a Rust library, so the bindings are generated
+/// in library mode from a wrapper crate of the app that depends on it with the `uniffi` feature and is
+/// built as a `cdylib` or `staticlib`, e.g. `uniffi-bindgen generate --library libwrapper.so --language kotlin`.
+#[derive(Debug, uniffi::Object)]
+pub struct DeviceIdService {
+    path: Option<PathBuf>,
+}
+
+#[uniffi::export]
+impl DeviceIdService {
+    /// Creates a service storing the device ID in the file at `storage_path`, or in the default location
+    /// if it is `None`.
+    #[uniffi::constructor]
+    pub fn new(storage_path: Option<String>) -> Arc<Self> {
+        Arc::new(Self {
+            path: storage_path.map(PathBuf::from),
+        })
+    }
+
+    /// Retrieves the device ID, generating and storing a new one if none is stored.
+    /// See [`DevDeviceId::get_or_generate`].
+    pub fn get_or_generate(&self) -> Result<String> {
+        let builder = DevDeviceIdBuilder::new();
+        let id = match &self.path {
+            Some(path) => builder.get_or_generate_at(path)?,
+            None => builder.get_or_generate()?,
+        };
+        Ok(id.to_string())
+    }
+
+    /// Retrieves the device ID, returning `None` if none is stored. See [`DevDeviceId::get`].
+    pub fn get(&self) -> Result<Option<String>> {
+        let builder = DevDeviceIdBuilder::new();
+        let id = match &self.path {
+            Some(path) => builder.get_at(path)?,
+            None => builder.get()?,
+        };
+        Ok(id.as_ref().map(DevDeviceId::to_string))
+    }
+
+    /// Removes the stored device ID, if any. See [`DevDeviceId::reset`].
+    pub fn reset(&self) -> Result<()> {
+        match &self.path {
+            Some(path) => FileStorage::at(path).clear(),
+            None => DevDeviceIdBuilder::new().reset(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Fixture;
+
+    #[test]
+    fn test_service_with_storage_path() {
+        let fixture = Fixture::new();
+        let path = fixture.dir().join("deviceid");
+        let service = DeviceIdService::new(Some(path.to_str().unwrap().to_string()));
+        assert_eq!(service.get().unwrap(), None);
+
+        let id = service.get_or_generate().unwrap();
+        assert_eq!(id.parse::<DevDeviceId>().unwrap().to_string(), id);
+        assert_eq!(service.get().unwrap(), Some(id.clone()));
+        assert_eq!(service.get_or_generate().unwrap(), id);
+
+        service.reset().unwrap();
+        assert_eq!(service.get().unwrap(), None);
+    }
+}
@@ -0,0 +1,1004 @@
+//! Storage in a file at a given path, usable on every platform; see [`FileStorage`].
+
+use crate::{
+    Clock, DevDeviceId, DevDeviceIdBuilder, Error, Metadata, Result, Storage, StorageLocation,
+    StoredFormat, SystemClock, storage,
+};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+const METADATA_EXTENSION: &str = ".meta";
+const SEALED_EXTENSION: &str = ".sealed";
+const TEMP_EXTENSION: &str = ".tmp";
+/// Most bytes accepted as a stored ID: the longest textual UUID form (URN, 45 bytes) plus whitespace.
+const MAX_ID_LEN: u64 = 64;
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Stores the device ID in the file at a given path, e.g. on a mounted volume.
+///
+/// This is the default storage on Unix, as `UnixStorage`, and can be used on any platform with
+/// [`FileStorage::at`] or [`DevDeviceId::get_or_generate_at`].
+#[derive(Debug, Clone)]
+pub struct FileStorage {
+    path: PathBuf,
+    format: StoredFormat,
+    record_metadata: bool,
+    durable: bool,
+    read_only: bool,
+    clock: Arc<dyn Clock>,
+}
+
+impl FileStorage {
+    /// Creates a storage for the file at `path`. Missing parent directories are created on store.
+    pub fn at(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            format: StoredFormat::default(),
+            record_metadata: false,
+            durable: false,
+            read_only: false,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Sets the form written by [`Storage::store`]. Defaults to [`StoredFormat::Hyphenated`].
+    pub fn stored_format(mut self, format: StoredFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Writes [`Metadata`] to a sibling `.meta` file when storing. Defaults to `false`.
+    pub fn record_metadata(mut self, record: bool) -> Self {
+        self.record_metadata = record;
+        self
+    }
+
+    /// Calls `fsync` on the file, and on Unix its directory, after storing. Defaults to `false`.
+    /// See [`DevDeviceIdBuilder::durable`].
+    pub fn durable(mut self, durable: bool) -> Self {
+        self.durable = durable;
+        self
+    }
+
+    /// Fails every write with [`Error::ReadOnly`], so that no file or directory is
+    /// ever created or removed. Defaults to `false`. See [`DevDeviceIdBuilder::strict_readonly`].
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Sets the clock timestamping the [`Metadata`]. Defaults to [`SystemClock`].
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// The path of the file holding the device ID.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The location of the file, for reports.
+    pub fn location(&self) -> StorageLocation {
+        StorageLocation::File(self.path.clone())
+    }
+
+    /// Parses a device ID from `reader`, e.g. a file descriptor inherited from a parent process,
+    /// with the same rules as [`Storage::retrieve`]: at most a few dozen bytes are read,
+    /// and a leading UTF-8 byte order mark and surrounding whitespace are ignored.
+    pub fn retrieve_from(reader: impl Read) -> Result<DevDeviceId> {
+        Self::read_id(reader)?
+            .ok_or_else(|| Error::BadUuidFormat("stored device ID is empty".to_string()))
+    }
+
+    /// Parses a device ID from `reader`, treating empty or blank contents as unset.
+    fn read_id(reader: impl Read) -> Result<Option<DevDeviceId>> {
+        let mut data = Vec::new();
+        reader
+            .take(MAX_ID_LEN + 1)
+            .read_to_end(&mut data)
+            .map_err(|e| Error::storage("read_file", e))?;
+        if data.len() as u64 > MAX_ID_LEN {
+            return Err(Error::BadUuidFormat(format!(
+                "stored device ID is longer than {MAX_ID_LEN} bytes"
+            )));
+        }
+        let read_len = data.len();
+        // tolerate a UTF-8 byte order mark and surrounding whitespace from hand-edited files
+        let data = data.strip_prefix(UTF8_BOM).unwrap_or(&data).trim_ascii();
+        if data.is_empty() {
+            return Ok(None);
+        }
+        if !data.is_ascii() {
+            return Err(Error::BadUuidFormat(format!(
+                "stored device ID is not ASCII UUID text ({} bytes), the file may be corrupt",
+                data.len()
+            )));
+        }
+        let text = std::str::from_utf8(data).map_err(|e| Error::BadUuidFormat(e.to_string()))?;
+        StoredFormat::decode_stored(text, read_len).map(Some)
+    }
+
+    /// Reads the [`Metadata`] file next to the device ID, returning `None` if it is absent or malformed.
+    pub fn metadata(&self) -> Result<Option<Metadata>> {
+        match std::fs::read_to_string(self.metadata_path()) {
+            Ok(json) => Ok(Metadata::from_json(&json)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData => Ok(None),
+            Err(e) => Err(Error::storage("read_metadata", e)),
+        }
+    }
+
+    /// Checks that the device ID can be read and stored, without storing one: the stored ID, if any,
+    /// must be readable, and a probe file is created and removed in the directory of the ID file,
+    /// or in its nearest existing ancestor if missing directories would be created on store.
+    pub fn preflight(&self) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        let folder = self.path.parent().unwrap_or(Path::new("."));
+        let Some(existing) = folder.ancestors().find(|dir| dir.exists()) else {
+            return Err(Error::storage("preflight", "no existing parent directory"));
+        };
+        if !existing.is_dir() {
+            return Err(Error::storage(
+                "create_dir",
+                format!("{} is not a directory", existing.display()),
+            ));
+        }
+        let probe = TempFile::new(&existing.join(self.path.file_name().unwrap_or_default()));
+        std::fs::File::create(&probe.path).map_err(|e| Error::storage("write_file", e))?;
+        self.read().map(drop)
+    }
+
+    /// Writes a sibling `.sealed` marker file and makes the file read-only, so that
+    /// [`Storage::overwrite`] and [`Storage::clear`] fail with [`Error::Sealed`]. See [`DevDeviceId::seal`].
+    pub fn seal(&self) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        if self.read()?.is_none() {
+            return Err(Error::storage("seal", "no device ID is stored"));
+        }
+        let seal_error = |e| Error::storage("seal", e);
+        std::fs::write(self.sealed_path(), b"").map_err(seal_error)?;
+        let mut permissions = std::fs::metadata(&self.path)
+            .map_err(seal_error)?
+            .permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(&self.path, permissions).map_err(seal_error)
+    }
+
+    /// Undoes [`FileStorage::seal`]: removes the marker file and makes the file writable by its owner
+    /// again. Does nothing if the ID is not sealed. See [`DevDeviceId::unseal`].
+    pub fn unseal(&self) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        if !self.is_sealed()? {
+            return Ok(());
+        }
+        match storage::make_writable(&self.path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(Error::storage("unseal", e)),
+        }
+        std::fs::remove_file(self.sealed_path()).map_err(|e| Error::storage("unseal", e))
+    }
+
+    /// Reports whether the marker file written by [`FileStorage::seal`] is present. A file that is
+    /// merely read-only, e.g. written by another tool, is not sealed.
+    pub fn is_sealed(&self) -> Result<bool> {
+        match std::fs::metadata(self.sealed_path()) {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(Error::storage("read_file", e)),
+        }
+    }
+
+    fn read(&self) -> Result<Option<DevDeviceId>> {
+        let file = match std::fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(Error::storage("read_file", e)),
+        };
+        Self::read_id(file)
+    }
+
+    fn metadata_path(&self) -> PathBuf {
+        let mut name = self.path.file_name().unwrap_or_default().to_os_string();
+        name.push(METADATA_EXTENSION);
+        self.path.with_file_name(name)
+    }
+
+    fn sealed_path(&self) -> PathBuf {
+        let mut name = self.path.file_name().unwrap_or_default().to_os_string();
+        name.push(SEALED_EXTENSION);
+        self.path.with_file_name(name)
+    }
+
+    fn write_id(&self, id: &DevDeviceId, overwrite: bool) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        // storing over a sealed ID fails with `AlreadySet` below, as for any stored ID
+        if overwrite && self.is_sealed()? {
+            return Err(Error::Sealed);
+        }
+        let created = self.create_parent_dirs()?;
+        if !overwrite {
+            // a blank file is unset, as when reading it, but a corrupt one is not
+            match self.read() {
+                Ok(None) => {}
+                Ok(Some(_)) | Err(Error::BadUuidFormat(_)) => return Err(Error::AlreadySet),
+                Err(err) => return Err(err),
+            }
+        }
+        let id_str = self.format.encode(id);
+        if let Err(err) = self.write_file(id_str.as_bytes(), overwrite) {
+            // don't leave directories behind that hold nothing, e.g. when only creating files is denied
+            for dir in created {
+                if std::fs::remove_dir(dir).is_err() {
+                    break;
+                }
+            }
+            return Err(err);
+        }
+        if self.record_metadata {
+            // best-effort: the ID is already stored, and metadata is only informational
+            let metadata = Metadata::at(self.clock.now());
+            let _ = std::fs::write(self.metadata_path(), metadata.to_json());
+        }
+        Ok(())
+    }
+
+    /// Creates the missing parent directories of the file, returning those it created, deepest first.
+    fn create_parent_dirs(&self) -> Result<Vec<PathBuf>> {
+        let Some(folder) = self.path.parent() else {
+            return Ok(Vec::new());
+        };
+        let missing = folder
+            .ancestors()
+            .take_while(|dir| !dir.as_os_str().is_empty() && !dir.exists())
+            .map(Path::to_path_buf)
+            .collect();
+        std::fs::create_dir_all(folder).map_err(|e| Error::storage("create_dir", e))?;
+        Ok(missing)
+    }
+
+    /// Writes `data` to a temporary file next to the target and moves it into place,
+    /// so that readers never see a partially written ID.
+    ///
+    /// Unless `overwrite` is set, the temporary file is hard-linked to the target, which fails if the
+    /// target exists, so that of several processes or threads storing at once exactly one succeeds and
+    /// the others fail with [`Error::AlreadySet`]. Only a blank target, which is unset, is replaced.
+    /// Where hard links are unsupported, e.g. on FAT volumes and some network mounts, the target is
+    /// created exclusively and written in place instead.
+    fn write_file(&self, data: &[u8], overwrite: bool) -> Result<()> {
+        self.write_file_with(data, overwrite, |from, to| std::fs::hard_link(from, to))
+    }
+
+    /// [`FileStorage::write_file`], creating hard links with `link`.
+    fn write_file_with(
+        &self,
+        data: &[u8],
+        overwrite: bool,
+        link: impl Fn(&Path, &Path) -> std::io::Result<()>,
+    ) -> Result<()> {
+        let folder = self.path.parent().unwrap_or(Path::new("."));
+        // the directory may exist but belong to another user, e.g. after an elevated run
+        let write_error = |e: std::io::Error| match e.kind() {
+            std::io::ErrorKind::PermissionDenied => Error::PermissionDenied {
+                path: folder.to_path_buf(),
+            },
+            _ => Error::storage("write_file", format!("{}: {e}", self.path.display())),
+        };
+        // moving the written file into place is a step of its own, e.g. the target may be a directory
+        let publish_error = |operation: &str, e: std::io::Error| {
+            Error::storage(operation, format!("{}: {e}", self.path.display()))
+        };
+        let temp = TempFile::new(&self.path);
+        {
+            // closed before the rename, which Windows requires
+            let mut file = std::fs::File::create(&temp.path).map_err(write_error)?;
+            file.write_all(data).map_err(write_error)?;
+            if self.durable {
+                file.sync_all()
+                    .map_err(|e| Error::storage("sync_file", e))?;
+            }
+        }
+        if overwrite {
+            std::fs::rename(&temp.path, &self.path).map_err(|e| publish_error("rename", e))?;
+            temp.commit();
+        } else {
+            // the temporary file is removed when dropped, leaving only the link
+            let published = match link(&temp.path, &self.path) {
+                Err(e) if link_unsupported(&e) => {
+                    self.create_exclusive(data).map_err(|e| ("create_file", e))
+                }
+                result => result.map_err(|e| ("link_file", e)),
+            };
+            match published {
+                Ok(()) => {}
+                Err((_, e)) if e.kind() == std::io::ErrorKind::AlreadyExists => match self.read() {
+                    Ok(None) => {
+                        std::fs::rename(&temp.path, &self.path)
+                            .map_err(|e| publish_error("rename", e))?;
+                        temp.commit();
+                    }
+                    Ok(Some(_)) | Err(Error::BadUuidFormat(_)) => return Err(Error::AlreadySet),
+                    Err(err) => return Err(err),
+                },
+                Err((operation, e)) => return Err(publish_error(operation, e)),
+            }
+        }
+        if self.durable {
+            // persist the directory entry too, not just the file contents
+            if let Some(folder) = self.path.parent() {
+                storage::sync_dir(folder).map_err(|e| Error::storage("sync_dir", e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates the target, failing if it exists, and writes `data` to it. Unlike a link, this lets
+    /// readers see the file before it is written, but an empty file reads as unset.
+    fn create_exclusive(&self, data: &[u8]) -> std::io::Result<()> {
+        let written = {
+            // closed before removing it on failure, which Windows requires
+            let mut file = std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&self.path)?;
+            file.write_all(data).and_then(|()| {
+                if self.durable {
+                    file.sync_all()
+                } else {
+                    Ok(())
+                }
+            })
+        };
+        if written.is_err() {
+            // best-effort: a partial ID would read as corrupt
+            let _ = std::fs::remove_file(&self.path);
+        }
+        written
+    }
+
+    /// The storage for the file at `path`, with the options of `builder`.
+    pub(crate) fn configured_at(builder: &DevDeviceIdBuilder, path: impl Into<PathBuf>) -> Self {
+        let storage = Self::at(path)
+            .stored_format(builder.stored_format)
+            .record_metadata(builder.record_metadata)
+            .durable(builder.durable)
+            .read_only(builder.strict_readonly);
+        match &builder.clock {
+            Some(clock) => Self {
+                clock: clock.clone(),
+                ..storage
+            },
+            None => storage,
+        }
+    }
+}
+
+/// Reports whether `e`, from creating a hard link, means the file system does not support them:
+/// Linux reports `EPERM` for file systems such as FAT, and the temporary file is known to be writable.
+fn link_unsupported(e: &std::io::Error) -> bool {
+    matches!(
+        e.kind(),
+        std::io::ErrorKind::Unsupported | std::io::ErrorKind::PermissionDenied
+    )
+}
+
+/// A temporary file that is removed when dropped, unless [`TempFile::commit`] is called
+/// after it has been renamed into place.
+struct TempFile {
+    path: PathBuf,
+    committed: bool,
+}
+
+impl TempFile {
+    /// A temporary file next to `target`, named uniquely within and across processes,
+    /// e.g. `deviceid.1234.0.tmp`.
+    fn new(target: &Path) -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let mut name = target.file_name().unwrap_or_default().to_os_string();
+        let n = NEXT.fetch_add(1, Ordering::Relaxed);
+        name.push(format!(".{}.{n}{TEMP_EXTENSION}", std::process::id()));
+        Self {
+            path: target.with_file_name(name),
+            committed: false,
+        }
+    }
+
+    fn commit(mut self) {
+        self.committed = true;
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            // best-effort: the original error is the one worth reporting
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+impl Storage for FileStorage {
+    fn retrieve(&mut self) -> Result<Option<DevDeviceId>> {
+        self.read()
+    }
+
+    fn store(&mut self, id: &DevDeviceId) -> Result<()> {
+        self.write_id(id, false)
+    }
+
+    fn overwrite(&mut self, id: &DevDeviceId) -> Result<()> {
+        self.write_id(id, true)
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        if self.is_sealed()? {
+            return Err(Error::Sealed);
+        }
+        match std::fs::remove_file(&self.path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(Error::storage("remove_file", e)),
+        }
+        // best-effort, like writing it
+        let _ = std::fs::remove_file(self.metadata_path());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Fixture;
+
+    const FILENAME: &str = crate::STORAGE_KEY;
+
+    #[test]
+    fn test_seal() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        let mut storage = FileStorage::at(dir.join(FILENAME));
+        assert!(matches!(storage.seal(), Err(Error::StorageError(_))));
+        let id = DevDeviceId::generate();
+        storage.store(&id).unwrap();
+        assert!(!storage.is_sealed().unwrap());
+
+        storage.seal().unwrap();
+        assert!(storage.is_sealed().unwrap());
+        assert!(matches!(
+            storage.overwrite(&DevDeviceId::generate()),
+            Err(Error::Sealed)
+        ));
+        assert!(matches!(storage.clear(), Err(Error::Sealed)));
+        assert!(matches!(
+            storage.store(&DevDeviceId::generate()),
+            Err(Error::AlreadySet)
+        ));
+        assert_eq!(storage.retrieve().unwrap(), Some(id));
+
+        storage.unseal().unwrap();
+        assert!(!storage.is_sealed().unwrap());
+        assert!(
+            !std::fs::metadata(storage.path())
+                .unwrap()
+                .permissions()
+                .readonly()
+        );
+        let new_id = DevDeviceId::generate();
+        storage.overwrite(&new_id).unwrap();
+        assert_eq!(storage.retrieve().unwrap(), Some(new_id));
+        storage.clear().unwrap();
+        let entries: Vec<_> = std::fs::read_dir(dir).unwrap().collect();
+        assert!(entries.is_empty(), "{entries:?}");
+    }
+
+    #[test]
+    fn test_read_only_file_is_not_sealed() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        let mut storage = FileStorage::at(dir.join(FILENAME));
+        storage.store(&DevDeviceId::generate()).unwrap();
+        let mut permissions = std::fs::metadata(storage.path()).unwrap().permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(storage.path(), permissions).unwrap();
+
+        assert!(!storage.is_sealed().unwrap());
+        // Windows refuses to replace a read-only file, but not because it is sealed
+        let result = storage.overwrite(&DevDeviceId::generate());
+        assert!(!matches!(result, Err(Error::Sealed)), "{result:?}");
+        storage::make_writable(storage.path()).unwrap();
+    }
+
+    #[test]
+    fn test_get_repairing_corrupt_file() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        let mut storage = FileStorage::at(dir.join(FILENAME)).record_metadata(true);
+        std::fs::write(storage.path(), "not-a-uuid").unwrap();
+        std::fs::write(storage.metadata_path(), Metadata::current().to_json()).unwrap();
+
+        // the strict read keeps failing
+        assert!(matches!(storage.retrieve(), Err(Error::BadUuidFormat(_))));
+        assert!(matches!(storage.retrieve(), Err(Error::BadUuidFormat(_))));
+
+        assert_eq!(DevDeviceId::get_repairing_in(&mut storage).unwrap(), None);
+        assert!(!storage.path().exists());
+        assert!(!storage.metadata_path().exists());
+        let id = DevDeviceId::get_or_generate_in(&mut storage).unwrap();
+        assert_eq!(
+            DevDeviceId::get_repairing_in(&mut storage).unwrap(),
+            Some(id)
+        );
+    }
+
+    #[test]
+    fn test_stored_format_checked_detects_tampering() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        let mut storage = FileStorage::at(dir.join(FILENAME)).stored_format(StoredFormat::Checked);
+        storage.store(&DevDeviceId::from_u128(1)).unwrap();
+        let tampered = std::fs::read_to_string(storage.path())
+            .unwrap()
+            .replacen("0001:", "0002:", 1);
+        std::fs::write(storage.path(), tampered).unwrap();
+        assert!(matches!(
+            storage.retrieve(),
+            Err(Error::BadUuidFormat(msg)) if msg.contains("checksum")
+        ));
+    }
+
+    #[test]
+    fn test_stored_format_round_trip() {
+        for format in [
+            StoredFormat::Hyphenated,
+            StoredFormat::Simple,
+            StoredFormat::Checked,
+        ] {
+            let fixture = Fixture::new();
+            let dir = fixture.dir();
+            let mut storage = FileStorage::at(dir.join(FILENAME)).stored_format(format);
+            let id = DevDeviceId::generate();
+            storage.store(&id).unwrap();
+            assert_eq!(
+                std::fs::read_to_string(storage.path()).unwrap(),
+                format.encode(&id)
+            );
+            assert_eq!(storage.retrieve().unwrap(), Some(id));
+        }
+    }
+
+    #[test]
+    fn test_stored_form_is_lowercase_hyphenated() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        let mut storage = FileStorage::at(dir.join(FILENAME));
+        let mut ids = vec![
+            DevDeviceId(uuid::Uuid::from_u128(u128::MAX)),
+            DevDeviceId(uuid::Uuid::from_u128(
+                0xABCDEF00_ABCD_4EF0_ABCD_EF00ABCDEF00,
+            )),
+        ];
+        for _ in 0..100 {
+            let upper = DevDeviceId::generate().to_string().to_ascii_uppercase();
+            ids.push(DevDeviceId(uuid::Uuid::parse_str(&upper).unwrap()));
+            ids.push(DevDeviceId::generate());
+        }
+        for id in ids {
+            storage.overwrite(&id).unwrap();
+            let stored = std::fs::read(storage.path()).unwrap();
+            assert_eq!(stored.len(), 36);
+            for (i, byte) in stored.iter().enumerate() {
+                if [8, 13, 18, 23].contains(&i) {
+                    assert_eq!(*byte, b'-');
+                } else {
+                    assert!(matches!(byte, b'0'..=b'9' | b'a'..=b'f'), "{stored:?}");
+                }
+            }
+            assert_eq!(storage.retrieve().unwrap(), Some(id));
+        }
+    }
+
+    #[test]
+    fn test_simple_format_has_no_hyphens() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        let mut storage = FileStorage::at(dir.join(FILENAME)).stored_format(StoredFormat::Simple);
+        storage.store(&DevDeviceId::generate()).unwrap();
+        let stored = std::fs::read_to_string(storage.path()).unwrap();
+        assert_eq!(stored.len(), 32);
+        assert!(!stored.contains('-'));
+    }
+
+    #[test]
+    fn test_metadata_recorded() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        let mut storage = FileStorage::at(dir.join(FILENAME)).record_metadata(true);
+        let id = DevDeviceId::generate();
+        storage.store(&id).unwrap();
+        assert!(dir.join("deviceid.meta").exists());
+        let metadata = storage.metadata().unwrap().unwrap();
+        assert!(!metadata.is_other_version());
+        assert_eq!(storage.retrieve().unwrap(), Some(id));
+    }
+
+    #[test]
+    fn test_metadata_not_recorded_by_default() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        let mut storage = FileStorage::at(dir.join(FILENAME));
+        storage.store(&DevDeviceId::generate()).unwrap();
+        assert!(!dir.join("deviceid.meta").exists());
+        assert_eq!(storage.metadata().unwrap(), None);
+    }
+
+    #[test]
+    fn test_malformed_metadata_ignored() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        let mut storage = FileStorage::at(dir.join(FILENAME));
+        let id = DevDeviceId::generate();
+        storage.store(&id).unwrap();
+        std::fs::write(dir.join("deviceid.meta"), b"\xff{not json").unwrap();
+        assert_eq!(storage.metadata().unwrap(), None);
+        assert_eq!(storage.retrieve().unwrap(), Some(id));
+    }
+
+    #[test]
+    fn test_create_dir_failure_context() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        // a file where the folder should be makes creating the folder fail
+        std::fs::write(dir.join("blocker"), b"").unwrap();
+        let mut storage = FileStorage::at(dir.join("blocker").join("sub").join(FILENAME));
+        let result = storage.store(&DevDeviceId::generate());
+        assert!(
+            matches!(&result, Err(Error::StorageError(msg)) if msg.starts_with("create_dir failed:")),
+            "{result:?}"
+        );
+    }
+
+    #[test]
+    fn test_rename_failure_context() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        // a directory where the file should be makes moving the written file into place fail
+        std::fs::create_dir(dir.join(FILENAME)).unwrap();
+        let result = FileStorage::at(dir.join(FILENAME)).overwrite(&DevDeviceId::generate());
+        assert!(
+            matches!(&result, Err(Error::StorageError(msg))
+                if msg.starts_with("rename failed:") && !msg.contains("not writable")),
+            "{result:?}"
+        );
+    }
+
+    #[test]
+    fn test_failed_write_removes_created_dirs() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        std::fs::create_dir(dir.join("existing")).unwrap();
+        // the directories can be created, but a file name this long cannot
+        let path = dir
+            .join("existing")
+            .join("Microsoft")
+            .join("DeveloperTools")
+            .join("x".repeat(250));
+        let result = FileStorage::at(&path).store(&DevDeviceId::generate());
+        let path_display = path.display().to_string();
+        assert!(
+            matches!(&result, Err(Error::StorageError(msg))
+                if msg.starts_with("write_file failed:") && msg.contains(&path_display)),
+            "{result:?}"
+        );
+        assert!(!dir.join("existing").join("Microsoft").exists());
+        assert!(dir.join("existing").exists());
+    }
+
+    #[test]
+    fn test_failed_write_removes_temp_file() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        // a directory where the file should be makes renaming the temp file fail
+        std::fs::create_dir(dir.join(FILENAME)).unwrap();
+        let mut storage = FileStorage::at(dir.join(FILENAME));
+        for _ in 0..3 {
+            assert!(storage.overwrite(&DevDeviceId::generate()).is_err());
+        }
+        let entries: Vec<_> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(entries, [FILENAME]);
+    }
+
+    #[test]
+    fn test_write_leaves_no_temp_file() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        let mut storage = FileStorage::at(dir.join(FILENAME)).durable(true);
+        storage.store(&DevDeviceId::generate()).unwrap();
+        storage.overwrite(&DevDeviceId::generate()).unwrap();
+        let entries: Vec<_> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(entries, [FILENAME]);
+    }
+
+    #[test]
+    fn test_concurrent_store_converges() {
+        for _ in 0..20 {
+            let fixture = Fixture::new();
+            let dir = fixture.dir();
+            let path = dir.join(FILENAME);
+            let barrier = Arc::new(std::sync::Barrier::new(8));
+            let threads: Vec<_> = (0..8)
+                .map(|_| {
+                    let path = path.clone();
+                    let barrier = barrier.clone();
+                    std::thread::spawn(move || {
+                        barrier.wait();
+                        DevDeviceId::get_or_generate_in(&mut FileStorage::at(path))
+                    })
+                })
+                .collect();
+            let ids: Vec<_> = threads
+                .into_iter()
+                .map(|thread| thread.join().unwrap().unwrap())
+                .collect();
+            assert!(ids.iter().all(|id| *id == ids[0]), "{ids:?}");
+            assert_eq!(
+                FileStorage::at(&path).retrieve().unwrap(),
+                Some(ids[0].clone())
+            );
+            let entries: Vec<_> = std::fs::read_dir(dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name())
+                .collect();
+            assert_eq!(entries, [FILENAME]);
+        }
+    }
+
+    #[test]
+    fn test_store_does_not_replace_concurrent_id() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        let path = dir.join(FILENAME);
+        let first = DevDeviceId::generate();
+        FileStorage::at(&path).store(&first).unwrap();
+        // the exclusive link fails even when storing bypasses the read of the existing ID
+        let mut storage = FileStorage::at(&path);
+        let result = storage.write_file(DevDeviceId::generate().to_string().as_bytes(), false);
+        assert!(matches!(result, Err(Error::AlreadySet)), "{result:?}");
+        assert_eq!(storage.retrieve().unwrap(), Some(first));
+    }
+
+    #[test]
+    fn test_store_without_hard_links() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        let path = dir.join(FILENAME);
+        let unsupported = |_: &Path, _: &Path| Err(std::io::ErrorKind::Unsupported.into());
+        let storage = FileStorage::at(&path).durable(true);
+        let first = DevDeviceId::generate();
+        storage
+            .write_file_with(first.to_string().as_bytes(), false, unsupported)
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), first.to_string());
+
+        // still exclusive
+        let result = storage.write_file_with(
+            DevDeviceId::generate().to_string().as_bytes(),
+            false,
+            unsupported,
+        );
+        assert!(matches!(result, Err(Error::AlreadySet)), "{result:?}");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), first.to_string());
+
+        // a blank file is still replaced
+        std::fs::write(&path, "").unwrap();
+        let denied = |_: &Path, _: &Path| Err(std::io::ErrorKind::PermissionDenied.into());
+        let second = DevDeviceId::generate();
+        storage
+            .write_file_with(second.to_string().as_bytes(), false, denied)
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), second.to_string());
+        let entries: Vec<_> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(entries, [FILENAME]);
+    }
+
+    #[test]
+    fn test_empty_file_is_unset() {
+        for contents in ["", "\n", "\u{FEFF} \r\n"] {
+            let fixture = Fixture::new();
+            let dir = fixture.dir();
+            let mut storage = FileStorage::at(dir.join(FILENAME));
+            std::fs::write(storage.path(), contents).unwrap();
+            assert_eq!(storage.retrieve().unwrap(), None);
+            let id = DevDeviceId::get_or_generate_in(&mut storage).unwrap();
+            assert_eq!(
+                std::fs::read_to_string(storage.path()).unwrap(),
+                id.to_string()
+            );
+        }
+        let result = FileStorage::retrieve_from(std::io::Cursor::new(""));
+        assert!(matches!(result, Err(Error::BadUuidFormat(_))));
+    }
+
+    #[test]
+    fn test_preflight_writable() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        FileStorage::at(dir.join(FILENAME)).preflight().unwrap();
+        FileStorage::at(dir.join("missing/folders").join(FILENAME))
+            .preflight()
+            .unwrap();
+        assert_eq!(std::fs::read_dir(dir).unwrap().count(), 0);
+
+        let mut storage = FileStorage::at(dir.join(FILENAME));
+        let id = DevDeviceId::get_or_generate_in(&mut storage).unwrap();
+        storage.preflight().unwrap();
+        assert_eq!(storage.retrieve().unwrap(), Some(id));
+    }
+
+    #[test]
+    fn test_preflight_not_writable() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        let storage = FileStorage::at(dir.join(FILENAME)).read_only(true);
+        assert!(matches!(storage.preflight(), Err(Error::ReadOnly)));
+
+        std::fs::write(dir.join("blocker"), b"").unwrap();
+        let storage = FileStorage::at(dir.join("blocker").join("sub").join(FILENAME));
+        let result = storage.preflight();
+        assert!(
+            matches!(&result, Err(Error::StorageError(msg)) if msg.starts_with("create_dir failed:")),
+            "{result:?}"
+        );
+
+        std::fs::write(dir.join(FILENAME), "not-a-uuid").unwrap();
+        let result = FileStorage::at(dir.join(FILENAME)).preflight();
+        assert!(matches!(result, Err(Error::BadUuidFormat(_))));
+    }
+
+    #[test]
+    fn test_retrieve_from_reader() {
+        let id = DevDeviceId::generate();
+        let reader = std::io::Cursor::new(id.to_string());
+        assert_eq!(FileStorage::retrieve_from(reader).unwrap(), id);
+
+        let reader = std::io::Cursor::new(format!("{id}\r\n"));
+        assert_eq!(FileStorage::retrieve_from(reader).unwrap(), id);
+    }
+
+    #[test]
+    fn test_retrieve_binary_garbage() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        let mut storage = FileStorage::at(dir.join(FILENAME));
+        std::fs::write(storage.path(), b"\x00\xff\xfe\x80garbage\x9c").unwrap();
+        let result = storage.retrieve();
+        assert!(
+            matches!(&result, Err(Error::BadUuidFormat(msg)) if msg.contains("not ASCII UUID text (12 bytes)")),
+            "{result:?}"
+        );
+    }
+
+    #[test]
+    fn test_retrieve_truncated_reports_length() {
+        let id = DevDeviceId::generate().to_string();
+        let truncated = &id[..35];
+        let result = FileStorage::retrieve_from(truncated.as_bytes());
+        assert!(
+            matches!(&result, Err(Error::BadUuidFormat(msg)) if msg.contains("(read 35 bytes, nothing trimmed)")),
+            "{result:?}"
+        );
+
+        let result = FileStorage::retrieve_from(format!("{truncated}\n").as_bytes());
+        assert!(
+            matches!(&result, Err(Error::BadUuidFormat(msg)) if msg.contains("(read 36 bytes, 35 after trimming)")),
+            "{result:?}"
+        );
+    }
+
+    #[test]
+    fn test_retrieve_from_oversized_reader() {
+        let reader = std::io::Cursor::new(vec![b'a'; 1024 * 1024]);
+        let result = FileStorage::retrieve_from(reader);
+        assert!(matches!(result, Err(Error::BadUuidFormat(_))));
+
+        let id = DevDeviceId::generate();
+        let reader = std::io::Cursor::new(format!("{id}{}", " ".repeat(64)));
+        let result = FileStorage::retrieve_from(reader);
+        assert!(matches!(result, Err(Error::BadUuidFormat(_))));
+    }
+
+    #[test]
+    fn test_retrieve_ignores_trailing_newline() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        let id = DevDeviceId::generate();
+        std::fs::write(dir.join(FILENAME), format!("{id}\n")).unwrap();
+        let mut storage = FileStorage::at(dir.join(FILENAME));
+        assert_eq!(storage.retrieve().unwrap(), Some(id));
+    }
+
+    #[test]
+    fn test_retrieve_ignores_bom_and_leading_whitespace() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        let id = DevDeviceId::generate();
+        let mut storage = FileStorage::at(dir.join(FILENAME));
+        std::fs::write(storage.path(), format!("\u{FEFF}{id}\r\n")).unwrap();
+        assert_eq!(storage.retrieve().unwrap(), Some(id.clone()));
+        std::fs::write(storage.path(), format!("\u{FEFF} \t{id}")).unwrap();
+        assert_eq!(storage.retrieve().unwrap(), Some(id.clone()));
+        std::fs::write(storage.path(), format!("  {id}\n")).unwrap();
+        assert_eq!(storage.retrieve().unwrap(), Some(id));
+    }
+
+    #[test]
+    fn test_durable_store() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        let mut storage = FileStorage::at(dir.join(FILENAME)).durable(true);
+        let id = DevDeviceId::generate();
+        storage.store(&id).unwrap();
+        assert_eq!(storage.retrieve().unwrap(), Some(id));
+    }
+
+    #[test]
+    fn test_store_creates_missing_folders() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        let mut storage = FileStorage::at(dir.join("Microsoft/DeveloperTools").join(FILENAME));
+        let id = DevDeviceId::generate();
+        storage.store(&id).unwrap();
+        assert_eq!(storage.retrieve().unwrap(), Some(id));
+    }
+
+    #[test]
+    fn test_get_or_generate_at() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        let path = dir.join("config").join(FILENAME);
+        assert_eq!(DevDeviceId::get_at(&path).unwrap(), None);
+        let id = DevDeviceId::get_or_generate_at(&path).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), id.to_string());
+        assert_eq!(DevDeviceId::get_or_generate_at(&path).unwrap(), id);
+        assert_eq!(DevDeviceId::get_at(&path).unwrap(), Some(id));
+    }
+
+    #[test]
+    fn test_get_or_generate_at_uses_builder_options() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        let path = dir.join(FILENAME);
+        let builder = DevDeviceIdBuilder::new()
+            .stored_format(StoredFormat::Simple)
+            .record_metadata(true);
+        let id = builder.get_or_generate_at(&path).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            StoredFormat::Simple.encode(&id)
+        );
+        assert!(FileStorage::at(&path).metadata().unwrap().is_some());
+        assert_eq!(builder.get_at(&path).unwrap(), Some(id));
+    }
+}
@@ -0,0 +1,155 @@
+#![cfg(any(test, feature = "test-util"))]
+
+//! Isolated storage and a controllable clock for deterministic tests; see [`Fixture`].
+
+use crate::{Clock, DevDeviceId, DevDeviceIdBuilder, FileStorage};
+#[cfg(target_family = "windows")]
+use crate::{MockWindowsRegistry, RegistryStorage};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+
+/// A [`Clock`] that only moves when told to. Clones share the same time.
+#[derive(Debug, Clone)]
+pub struct TestClock {
+    now: Arc<Mutex<SystemTime>>,
+}
+
+impl TestClock {
+    /// Creates a clock stopped at `now`.
+    pub fn new(now: SystemTime) -> Self {
+        Self {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+
+    /// Sets the time.
+    pub fn set(&self, now: SystemTime) {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner) = now;
+    }
+
+    /// Moves the time forward by `by`.
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner) += by;
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// An isolated storage location and a [`TestClock`] for one test, so that tests neither touch the real
+/// device ID nor change the process environment, e.g. `HOME`.
+///
+/// The fixture is the teardown guard: its temporary directory is removed when it is dropped.
+///
+/// ```rust
+/// use deviceid::{DevDeviceId, Fixture};
+/// let fixture = Fixture::new();
+/// let mut storage = fixture.storage().record_metadata(true);
+/// let id = DevDeviceId::get_or_generate_in(&mut storage).unwrap();
+/// assert_eq!(DevDeviceId::get_in(&mut storage).unwrap(), Some(id));
+/// assert_eq!(storage.metadata().unwrap().unwrap().written_at, Fixture::START);
+/// ```
+#[derive(Debug)]
+pub struct Fixture {
+    dir: PathBuf,
+    clock: TestClock,
+    #[cfg(target_family = "windows")]
+    registry: MockWindowsRegistry,
+}
+
+impl Fixture {
+    /// The time the clock of a new fixture is stopped at, in seconds since the Unix epoch.
+    pub const START: u64 = 1_700_000_000;
+
+    /// Creates an empty temporary directory and a clock stopped at [`Fixture::START`].
+    pub fn new() -> Self {
+        let dir =
+            std::env::temp_dir().join(format!("deviceid-fixture-{}", DevDeviceId::generate()));
+        std::fs::create_dir_all(&dir).expect("failed to create the fixture directory");
+        Self {
+            dir,
+            clock: TestClock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(Self::START)),
+            #[cfg(target_family = "windows")]
+            registry: MockWindowsRegistry::new(),
+        }
+    }
+
+    /// The temporary directory, removed when the fixture is dropped.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The clock used by the storages and builder of this fixture.
+    pub fn clock(&self) -> &TestClock {
+        &self.clock
+    }
+
+    /// A builder with the clock of this fixture, e.g. for [`DevDeviceIdBuilder::get_or_generate_at`]
+    /// with a path in [`Fixture::dir`].
+    pub fn builder(&self) -> DevDeviceIdBuilder {
+        DevDeviceIdBuilder::new().clock(self.clock.clone())
+    }
+
+    /// A file storage in the temporary directory, with the clock of this fixture.
+    /// Every call returns a storage for the same file.
+    pub fn storage(&self) -> FileStorage {
+        FileStorage::configured_at(&self.builder(), self.dir.join(crate::STORAGE_KEY))
+    }
+
+    /// The in-memory registry behind [`Fixture::registry_storage`], to inspect or seed values.
+    #[cfg(target_family = "windows")]
+    pub fn registry(&self) -> &MockWindowsRegistry {
+        &self.registry
+    }
+
+    /// A registry storage backed by an in-memory registry, with the clock of this fixture.
+    /// Every call returns a storage for the same registry.
+    #[cfg(target_family = "windows")]
+    pub fn registry_storage(&self) -> RegistryStorage<MockWindowsRegistry> {
+        RegistryStorage::with_registry(self.registry.clone()).clock(self.clock.clone())
+    }
+}
+
+impl Default for Fixture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        // best-effort: a leftover temporary directory should not fail the test
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Storage;
+
+    #[test]
+    fn test_fixture_isolated_and_removed() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir().to_path_buf();
+        let id = DevDeviceId::get_or_generate_in(&mut fixture.storage()).unwrap();
+        assert_eq!(fixture.storage().retrieve().unwrap(), Some(id));
+        assert!(Fixture::new().storage().retrieve().unwrap().is_none());
+        drop(fixture);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn test_fixture_clock_timestamps_metadata() {
+        let fixture = Fixture::new();
+        fixture.clock().advance(Duration::from_secs(60));
+        let mut storage = fixture.storage().record_metadata(true);
+        storage.store(&DevDeviceId::generate()).unwrap();
+        let metadata = storage.metadata().unwrap().unwrap();
+        assert_eq!(metadata.written_at, Fixture::START + 60);
+    }
+}
@@ -0,0 +1,324 @@
+//! A device ID resolved once per process and shared between threads; see [`DeviceIdHandle`].
+
+use crate::{DevDeviceId, Result};
+use std::sync::atomic::{AtomicU64, Ordering, fence};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::time::Duration;
+
+static HANDLE: OnceLock<DeviceIdHandle> = OnceLock::new();
+static INIT: Mutex<()> = Mutex::new(());
+
+type Resolve = dyn Fn() -> Result<DevDeviceId> + Send + Sync;
+
+/// The device ID of this process, read or generated by the first [`DeviceIdHandle::init`].
+///
+/// Startup code in several subsystems can each call `init` without repeating the storage I/O
+/// or racing to generate: only the first call touches storage, and the others wait for it and
+/// share its result. A failed `init` is not cached, so a later call tries again.
+///
+/// Long-lived processes can pick up an ID that another tool reset or rewrote with
+/// [`DeviceIdHandle::refresh`] or [`DeviceIdHandle::refresh_every`].
+///
+/// ```no_run
+/// # fn main() -> deviceid::Result<()> {
+/// let handle = deviceid::DeviceIdHandle::init()?;
+/// println!("{}", handle.get());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct DeviceIdHandle {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    id: AtomicId,
+    /// Serializes refreshes, the only writers of `id`
+    refreshing: Mutex<()>,
+    resolve: Box<Resolve>,
+}
+
+/// A device ID that is read without locking, even while a refresh replaces it: a sequence lock,
+/// whose readers retry if a write overlapped their read.
+struct AtomicId {
+    /// Odd while a write is in progress
+    seq: AtomicU64,
+    high: AtomicU64,
+    low: AtomicU64,
+}
+
+impl AtomicId {
+    fn new(id: &DevDeviceId) -> Self {
+        let bits = id.0.as_u128();
+        Self {
+            seq: AtomicU64::new(0),
+            high: AtomicU64::new((bits >> 64) as u64),
+            low: AtomicU64::new(bits as u64),
+        }
+    }
+
+    fn load(&self) -> DevDeviceId {
+        loop {
+            let seq = self.seq.load(Ordering::Acquire);
+            if seq.is_multiple_of(2) {
+                let high = self.high.load(Ordering::Relaxed);
+                let low = self.low.load(Ordering::Relaxed);
+                fence(Ordering::Acquire);
+                if self.seq.load(Ordering::Relaxed) == seq {
+                    return DevDeviceId::from_u128(u128::from(high) << 64 | u128::from(low));
+                }
+            }
+            std::hint::spin_loop();
+        }
+    }
+
+    /// Replaces the ID; callers must not store concurrently.
+    fn store(&self, id: &DevDeviceId) {
+        let bits = id.0.as_u128();
+        let seq = self.seq.load(Ordering::Relaxed);
+        self.seq.store(seq.wrapping_add(1), Ordering::Relaxed);
+        fence(Ordering::Release);
+        self.high.store((bits >> 64) as u64, Ordering::Relaxed);
+        self.low.store(bits as u64, Ordering::Relaxed);
+        self.seq.store(seq.wrapping_add(2), Ordering::Release);
+    }
+}
+
+/// Stops the background refresh started by [`DeviceIdHandle::refresh_every`] when dropped.
+#[derive(Debug)]
+pub struct RefreshGuard {
+    _stop: mpsc::Sender<()>,
+}
+
+impl DeviceIdHandle {
+    /// Returns the handle of this process, calling [`DevDeviceId::get_or_generate`] the first time.
+    pub fn init() -> Result<Self> {
+        Self::init_once(&HANDLE, &INIT, DevDeviceId::get_or_generate)
+    }
+
+    /// The device ID, without any I/O or locking, even while a refresh replaces it.
+    pub fn get(&self) -> DevDeviceId {
+        self.shared.id.load()
+    }
+
+    /// Reads storage again, as [`DevDeviceId::get_or_generate`] does, and updates the handle and all
+    /// its clones. Returns the new device ID if it changed, e.g. because another tool reset it.
+    ///
+    /// On error, the handle keeps its device ID.
+    pub fn refresh(&self) -> Result<Option<DevDeviceId>> {
+        let id = (self.shared.resolve)()?;
+        let _guard = self
+            .shared
+            .refreshing
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if self.shared.id.load() == id {
+            return Ok(None);
+        }
+        self.shared.id.store(&id);
+        Ok(Some(id))
+    }
+
+    /// Calls [`DeviceIdHandle::refresh`] every `interval` on a background thread, and `on_change`
+    /// with the new device ID whenever it changed. Failed refreshes are skipped.
+    ///
+    /// The refresh stops when the returned guard is dropped.
+    pub fn refresh_every(
+        &self,
+        interval: Duration,
+        on_change: impl Fn(&DevDeviceId) + Send + 'static,
+    ) -> RefreshGuard {
+        let (stop, stopped) = mpsc::channel();
+        let handle = self.clone();
+        std::thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                if let Ok(Some(id)) = handle.refresh() {
+                    on_change(&id);
+                }
+            }
+        });
+        RefreshGuard { _stop: stop }
+    }
+
+    fn init_once(
+        cell: &OnceLock<Self>,
+        lock: &Mutex<()>,
+        resolve: impl Fn() -> Result<DevDeviceId> + Send + Sync + 'static,
+    ) -> Result<Self> {
+        if let Some(handle) = cell.get() {
+            return Ok(handle.clone());
+        }
+        // the lock serializes the I/O, so that only one caller resolves the ID
+        let _guard = lock.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(handle) = cell.get() {
+            return Ok(handle.clone());
+        }
+        let handle = Self {
+            shared: Arc::new(Shared {
+                id: AtomicId::new(&resolve()?),
+                refreshing: Mutex::new(()),
+                resolve: Box::new(resolve),
+            }),
+        };
+        Ok(cell.get_or_init(|| handle).clone())
+    }
+}
+
+impl std::fmt::Debug for DeviceIdHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeviceIdHandle")
+            .field("id", &self.get())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Error, MemoryStorage, Storage};
+    use std::sync::Barrier;
+    use std::sync::atomic::{AtomicBool, AtomicUsize};
+
+    #[test]
+    fn test_init_once_across_threads() {
+        struct CountingStorage<'a>(MemoryStorage, &'a AtomicUsize);
+
+        impl Storage for CountingStorage<'_> {
+            fn retrieve(&mut self) -> Result<Option<DevDeviceId>> {
+                self.0.retrieve()
+            }
+
+            fn store(&mut self, id: &DevDeviceId) -> Result<()> {
+                self.1.fetch_add(1, Ordering::SeqCst);
+                self.0.store(id)
+            }
+
+            fn overwrite(&mut self, id: &DevDeviceId) -> Result<()> {
+                self.1.fetch_add(1, Ordering::SeqCst);
+                self.0.overwrite(id)
+            }
+
+            fn clear(&mut self) -> Result<()> {
+                self.0.clear()
+            }
+        }
+
+        static CELL: OnceLock<DeviceIdHandle> = OnceLock::new();
+        static LOCK: Mutex<()> = Mutex::new(());
+        static WRITES: AtomicUsize = AtomicUsize::new(0);
+
+        let barrier = Arc::new(Barrier::new(16));
+        let threads: Vec<_> = (0..16)
+            .map(|_| {
+                let barrier = barrier.clone();
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    DeviceIdHandle::init_once(&CELL, &LOCK, || {
+                        // a fresh storage per call: a second resolve would store a different ID
+                        let mut storage = CountingStorage(MemoryStorage::new(), &WRITES);
+                        DevDeviceId::get_or_generate_in(&mut storage)
+                    })
+                    .unwrap()
+                    .get()
+                })
+            })
+            .collect();
+        let ids: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+        assert!(ids.iter().all(|id| *id == ids[0]));
+        assert_eq!(WRITES.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_failed_init_is_retried() {
+        let cell = OnceLock::new();
+        let lock = Mutex::new(());
+        let result = DeviceIdHandle::init_once(&cell, &lock, || Err(Error::ReadOnly));
+        assert!(matches!(result, Err(Error::ReadOnly)));
+
+        let id = DevDeviceId::generate();
+        let resolved = id.clone();
+        let handle = DeviceIdHandle::init_once(&cell, &lock, move || Ok(resolved.clone())).unwrap();
+        assert_eq!(handle.get(), id);
+        let again = DeviceIdHandle::init_once(&cell, &lock, || unreachable!()).unwrap();
+        assert_eq!(again.get(), id);
+    }
+
+    /// A handle resolving from `storage`, which the test can modify behind its back.
+    fn handle_in(storage: &Arc<Mutex<MemoryStorage>>) -> DeviceIdHandle {
+        let storage = storage.clone();
+        DeviceIdHandle::init_once(&OnceLock::new(), &Mutex::new(()), move || {
+            DevDeviceId::get_or_generate_in(&mut *storage.lock().unwrap())
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_refresh_observes_external_change() {
+        let storage = Arc::new(Mutex::new(MemoryStorage::new()));
+        let handle = handle_in(&storage);
+        let clone = handle.clone();
+        let id = handle.get();
+        assert_eq!(handle.refresh().unwrap(), None);
+
+        let new_id = DevDeviceId::generate();
+        storage.lock().unwrap().overwrite(&new_id).unwrap();
+        assert_eq!(handle.get(), id);
+        assert_eq!(handle.refresh().unwrap(), Some(new_id.clone()));
+        assert_eq!(handle.get(), new_id);
+        assert_eq!(clone.get(), new_id);
+
+        // a reset makes the refresh generate and store a new ID
+        storage.lock().unwrap().clear().unwrap();
+        let regenerated = handle.refresh().unwrap().unwrap();
+        assert_ne!(regenerated, new_id);
+        assert_eq!(
+            storage.lock().unwrap().retrieve().unwrap(),
+            Some(regenerated)
+        );
+    }
+
+    #[test]
+    fn test_get_during_refresh_is_never_torn() {
+        let ids = [DevDeviceId::from_u128(0), DevDeviceId::from_u128(u128::MAX)];
+        let toggle = AtomicBool::new(false);
+        let resolved = ids.clone();
+        let handle = DeviceIdHandle::init_once(&OnceLock::new(), &Mutex::new(()), move || {
+            Ok(resolved[usize::from(toggle.fetch_xor(true, Ordering::Relaxed))].clone())
+        })
+        .unwrap();
+        let refresher = handle.clone();
+        let refreshes = std::thread::spawn(move || {
+            for _ in 0..10_000 {
+                refresher.refresh().unwrap();
+            }
+        });
+        while !refreshes.is_finished() {
+            let id = handle.get();
+            assert!(ids.contains(&id), "{id}");
+        }
+        refreshes.join().unwrap();
+    }
+
+    #[test]
+    fn test_refresh_every_notifies_change() {
+        let storage = Arc::new(Mutex::new(MemoryStorage::new()));
+        let handle = handle_in(&storage);
+        let (tx, rx) = mpsc::channel();
+        let guard = handle.refresh_every(Duration::from_millis(10), move |id| {
+            let _ = tx.send(id.clone());
+        });
+
+        let new_id = DevDeviceId::generate();
+        storage.lock().unwrap().overwrite(&new_id).unwrap();
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), new_id);
+        assert_eq!(handle.get(), new_id);
+
+        // the refresh thread exits, dropping the callback and its sender
+        drop(guard);
+        assert_eq!(
+            rx.recv_timeout(Duration::from_secs(5)),
+            Err(RecvTimeoutError::Disconnected)
+        );
+    }
+}
@@ -0,0 +1,56 @@
+#![cfg(feature = "hashing")]
+
+use crate::DevDeviceId;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+impl DevDeviceId {
+    /// Derives a one-way identifier from the device ID, keyed by `salt`: the HMAC-SHA256 of the UUID bytes.
+    ///
+    /// The result is stable for a given salt but cannot be linked back to the device ID, nor across salts,
+    /// so callers can use a distinct salt per destination and never send the raw device ID.
+    pub fn hashed(&self, salt: &[u8]) -> [u8; 32] {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(salt).expect("HMAC accepts keys of any length");
+        mac.update(self.0.as_bytes());
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&mac.finalize().into_bytes());
+        hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_hashed_known_value() {
+        let id = DevDeviceId(Uuid::from_u128(0x550e8400_e29b_41d4_a716_446655440000));
+        let hex: String = id
+            .hashed(b"telemetry")
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        assert_eq!(
+            hex,
+            "48ea37f44a3f17bb20d4f877cfdf88890f267ec1d735d0f10610be6352165297"
+        );
+    }
+
+    #[test]
+    fn test_hashed_deterministic() {
+        let id = DevDeviceId::generate();
+        assert_eq!(id.hashed(b"salt"), id.hashed(b"salt"));
+    }
+
+    #[test]
+    fn test_hashed_diverges() {
+        let id = DevDeviceId::generate();
+        assert_ne!(id.hashed(b"salt-a"), id.hashed(b"salt-b"));
+        assert_ne!(
+            id.hashed(b"salt-a"),
+            DevDeviceId::generate().hashed(b"salt-a")
+        );
+    }
+}
@@ -0,0 +1,107 @@
+#![cfg(feature = "serde")]
+
+use crate::DevDeviceId;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The device ID together with the platform it belongs to, for syncing to a backend in one value.
+///
+/// Serializes as `{"deviceId":"...","platform":"...","generatedAt":...}`, omitting `generatedAt` when unknown.
+/// The bare [`DevDeviceId`] still serializes as a plain string.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceIdentity {
+    /// The device ID
+    pub device_id: DevDeviceId,
+    /// The operating system, as in [`std::env::consts::OS`], e.g. `linux`, `macos` or `windows`
+    pub platform: String,
+    /// When the envelope was created, in seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generated_at: Option<u64>,
+}
+
+impl DeviceIdentity {
+    /// Wraps the device ID for the current platform, stamped with the current time.
+    pub fn new(device_id: DevDeviceId) -> Self {
+        Self {
+            device_id,
+            platform: std::env::consts::OS.to_string(),
+            generated_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .ok(),
+        }
+    }
+}
+
+/// The device ID nested under a `value` field, i.e. `{"value":"..."}`, for schemas that do not accept
+/// the bare string that [`DevDeviceId`] serializes to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct NamedDevDeviceId {
+    /// The device ID
+    pub value: DevDeviceId,
+}
+
+impl From<DevDeviceId> for NamedDevDeviceId {
+    fn from(value: DevDeviceId) -> Self {
+        Self { value }
+    }
+}
+
+impl From<NamedDevDeviceId> for DevDeviceId {
+    fn from(named: NamedDevDeviceId) -> Self {
+        named.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_round_trip() {
+        let identity = DeviceIdentity::new(DevDeviceId::generate());
+        assert_eq!(identity.platform, std::env::consts::OS);
+        let json = serde_json::to_string(&identity).unwrap();
+        assert_eq!(
+            serde_json::from_str::<DeviceIdentity>(&json).unwrap(),
+            identity
+        );
+    }
+
+    #[test]
+    fn test_field_names() {
+        let identity = DeviceIdentity {
+            device_id: DevDeviceId(Uuid::from_u128(0x550e8400_e29b_41d4_a716_446655440000)),
+            platform: "linux".to_string(),
+            generated_at: Some(1700000000),
+        };
+        assert_eq!(
+            serde_json::to_string(&identity).unwrap(),
+            r#"{"deviceId":"550e8400-e29b-41d4-a716-446655440000","platform":"linux","generatedAt":1700000000}"#
+        );
+    }
+
+    #[test]
+    fn test_without_timestamp() {
+        let json = r#"{"deviceId":"550e8400-e29b-41d4-a716-446655440000","platform":"windows"}"#;
+        let identity: DeviceIdentity = serde_json::from_str(json).unwrap();
+        assert_eq!(identity.generated_at, None);
+        assert_eq!(serde_json::to_string(&identity).unwrap(), json);
+    }
+
+    #[test]
+    fn test_named_round_trip() {
+        let id = DevDeviceId(Uuid::from_u128(0x550e8400_e29b_41d4_a716_446655440000));
+        let json = serde_json::to_string(&NamedDevDeviceId::from(id.clone())).unwrap();
+        assert_eq!(json, r#"{"value":"550e8400-e29b-41d4-a716-446655440000"}"#);
+        let named: NamedDevDeviceId = serde_json::from_str(&json).unwrap();
+        assert_eq!(DevDeviceId::from(named), id);
+
+        // the bare device ID stays transparent
+        let json = serde_json::to_string(&id).unwrap();
+        assert_eq!(json, r#""550e8400-e29b-41d4-a716-446655440000""#);
+        assert_eq!(serde_json::from_str::<DevDeviceId>(&json).unwrap(), id);
+    }
+}
@@ -12,33 +12,149 @@
 //! assert_eq!(device_id, device_id2.unwrap());
 //! ```
 //!
+//! The device ID is stored in a platform-specific location by default (see [`DefaultStorage`]).
+//! Other backends can be used through the [`Storage`] trait, e.g. [`MemoryStorage`]:
+//!
+//! ```rust
+//! use deviceid::{DevDeviceId, MemoryStorage};
+//! let mut storage = MemoryStorage::new();
+//! let device_id = DevDeviceId::get_or_generate_in(&mut storage).unwrap();
+//! assert_eq!(DevDeviceId::get_in(&mut storage).unwrap(), Some(device_id));
+//! ```
+//!
 //! Optional features:
-//! - `serde`: (default) Enables serialization and deserialization of `DevDeviceId` using Serde
+//! - `serde`: (default) Enables serialization and deserialization of `DevDeviceId` using Serde,
+//!   and the `DeviceIdentity` envelope
+//! - `hashing`: Enables `DevDeviceId::hashed`, a keyed one-way hash of the ID (HMAC-SHA256), and
+//!   `DevDeviceId::export_token`/`import_token`, carrying the ID to another machine in a signed token
+//! - `metrics`: Counts generated and retrieved IDs and errors through the `metrics` crate, as
+//!   `devdeviceid.generated`, `devdeviceid.retrieved` and `devdeviceid.error{kind=...}`
+//! - `log`: Logs the byte lengths behind a stored device ID that fails to parse through the `log` crate,
+//!   at debug level, so truncated files and registry values are visible in application logs
+//! - `mac-fallback`: Enables `DevDeviceId::from_mac_fallback`, an ID derived from a stable MAC address
+//!   when storage is unavailable, logging the fallback through the `log` crate
+//! - `watch`: Enables `DevDeviceId::watch`, reporting changes to the stored ID made by any process,
+//!   through the `notify` crate on Linux and macOS and by polling elsewhere
+//! - `uniffi`: Enables `DeviceIdService`, exposing `get_or_generate`, `get` and `reset` and the `Error` enum
+//!   to Kotlin and Swift apps through UniFFI
+//! - `test-util`: Enables test helpers for downstream tests: `Fixture`, an isolated storage directory with
+//!   a controllable `TestClock`, and `MockWindowsRegistry` on Windows
 //!
 //! **Note**: This crate assumes that the device ID is unlikely to be stored by multiple applications at once,
 //! so it does not go to great lengths to ensure that it does not overwrite an existing ID.
+// the storage helpers have no users on targets without storage
+#![cfg_attr(
+    not(any(target_family = "unix", target_family = "windows", target_os = "wasi")),
+    allow(dead_code)
+)]
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 use uuid::Uuid;
 
 /// A unique identifier for a device, generated or retrieved from storage.
 ///
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// IDs are ordered by the bytes of the underlying UUID.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(feature = "serde", serde(transparent))]
 pub struct DevDeviceId(Uuid);
 
+mod builder;
+mod clock;
+mod counters;
+mod ffi;
+mod file;
+mod fixture;
+mod handle;
+mod hashing;
+mod identity;
+mod location;
+mod mac;
+mod metadata;
+mod short_code;
+mod storage;
+mod token;
 mod unix;
+mod unsupported;
+mod watch;
 mod windows;
 
-mod storage {
-    #[cfg(target_family = "unix")]
-    pub use super::unix::*;
-    #[cfg(target_family = "windows")]
-    pub use super::windows::*;
+pub use builder::DevDeviceIdBuilder;
+pub use clock::{Clock, SystemClock};
+#[cfg(feature = "uniffi")]
+pub use ffi::DeviceIdService;
+#[cfg(feature = "test-util")]
+pub use fixture::{Fixture, TestClock};
+pub use handle::{DeviceIdHandle, RefreshGuard};
+#[cfg(feature = "serde")]
+pub use identity::{DeviceIdentity, NamedDevDeviceId};
+pub use location::StorageLocation;
+pub use metadata::Metadata;
+#[cfg(all(target_family = "windows", feature = "test-util"))]
+pub use storage::MockWindowsRegistry;
+#[cfg(any(target_family = "unix", target_os = "wasi"))]
+pub use storage::UnixStorage;
+#[cfg(not(any(target_family = "unix", target_family = "windows", target_os = "wasi")))]
+pub use storage::UnsupportedStorage;
+pub use storage::{
+    DefaultStorage, FileStorage, MemoryStorage, MirrorStorage, Storage, StorageKind, ensure_stored,
+};
+#[cfg(target_family = "windows")]
+pub use storage::{LiveRegistry, Registry, RegistryStorage};
+#[cfg(feature = "watch")]
+pub use watch::{DeviceIdWatcher, WatchEvent};
+
+#[cfg(feature = "uniffi")]
+uniffi::setup_scaffolding!();
+
+/// Directory holding the device ID on Unix, relative to the platform root
+/// (`$XDG_CACHE_HOME` or `$HOME/.cache` on Linux, `$HOME/Library/Application Support` on macOS,
+/// and `$XDG_STATE_HOME` or `$HOME/.local/state` on other Unix systems).
+pub const UNIX_SUBPATH: &str = "Microsoft/DeveloperTools";
+/// Registry key holding the device ID on Windows, under `HKEY_CURRENT_USER`.
+pub const WINDOWS_REGISTRY_PATH: &str = r"SOFTWARE\Microsoft\DeveloperTools";
+/// Name of the file (Unix) or registry value (Windows) holding the device ID.
+pub const STORAGE_KEY: &str = "deviceid";
+/// Environment variable overriding the Application Support directory on macOS, e.g. for sandboxes
+/// and tests, without changing `HOME`. It must be an absolute path; the device ID is stored under
+/// [`UNIX_SUBPATH`] inside it. Other platforms ignore it.
+pub const MACOS_SUPPORT_DIR_ENV_VAR: &str = "DEVDEVICEID_MACOS_SUPPORT_DIR";
+/// Environment variable that, when set to `1`, stops [`DevDeviceId::get_or_generate`] from persisting:
+/// a stored ID is still returned, but if there is none a new ID is generated and returned without
+/// being stored, on every platform.
+///
+/// An ID supplied through [`DevDeviceId::from_env_or_generate`] takes precedence over this flag,
+/// and is never persisted while it is set, even with [`DevDeviceIdBuilder::persist_env_id`].
+pub const EPHEMERAL_ENV_VAR: &str = "DEVDEVICEID_EPHEMERAL";
+
+/// Looks up an environment variable, as [`std::env::var_os`] does, so tests can pass a fake environment.
+pub(crate) type Env<'a> = &'a dyn Fn(&str) -> Option<OsString>;
+
+/// The [`Env`] of the process.
+pub(crate) fn process_env(name: &str) -> Option<OsString> {
+    std::env::var_os(name)
+}
+
+/// An [`Env`] holding only `vars`.
+#[cfg(test)]
+pub(crate) fn fake_env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<OsString> + use<> {
+    let vars: Vec<(String, OsString)> = vars
+        .iter()
+        .map(|(key, value)| (key.to_string(), OsString::from(value)))
+        .collect();
+    move |name| {
+        vars.iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.clone())
+    }
 }
 
 /// Errors that can occur while retrieving or generating a device ID.
+///
+/// With the `uniffi` feature, foreign code sees the variants without their fields, with this message.
 #[derive(Debug, Error)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Error), uniffi(flat_error))]
 pub enum Error {
     /// Error with the underlying storage mechanism (file I/O on Unix, or registry on Windows)
     #[error("Failed to store or retrieve device ID due to storage error: {0}")]
@@ -49,33 +165,627 @@ pub enum Error {
     /// Error when the device ID is already set and cannot be generated again
     #[error("Device ID is already set")]
     AlreadySet,
+    /// Error when a [`DevDeviceIdBuilder`] option has an invalid value
+    #[error("Invalid configuration: {0}")]
+    InvalidConfiguration(String),
+    /// Error when accessing storage did not complete within [`DevDeviceIdBuilder::io_timeout`]
+    #[error("Storage access timed out after {0:?}")]
+    Timeout(std::time::Duration),
+    /// Error when storing the device ID is refused by [`DevDeviceIdBuilder::strict_readonly`]
+    #[error("Storage is read-only, so the device ID cannot be stored")]
+    ReadOnly,
+    /// Error when storing a device ID that is not acceptable, e.g. the nil UUID
+    #[error("Invalid device ID: {0}")]
+    InvalidId(String),
+    /// Error when the default storage is not available on this target, e.g. `wasm32-unknown-unknown`
+    #[error("Device ID storage is not supported on {platform}")]
+    Unsupported {
+        /// The target architecture and operating system, e.g. `wasm32-unknown`
+        platform: String,
+    },
+    /// Error when overwriting or removing a device ID sealed with [`DevDeviceId::seal`]
+    #[error("Device ID is sealed, so it cannot be overwritten or removed")]
+    Sealed,
+    /// Error when storage returns a different device ID, or none, right after storing one, e.g. a faulty
+    /// custom [`Storage`] backend
+    #[error(
+        "Stored device ID {expected}, but storage returned {}",
+        .actual.as_ref().map_or("no device ID".to_string(), DevDeviceId::to_string)
+    )]
+    VerificationFailed {
+        /// The device ID that was stored
+        expected: DevDeviceId,
+        /// The device ID read back afterwards, `None` if the storage returned none
+        actual: Option<DevDeviceId>,
+    },
+    /// Error when the directory that holds the device ID exists but cannot be written, e.g. because
+    /// another user or an elevated run created it
+    #[error("Permission denied: directory {} is not writable", .path.display())]
+    PermissionDenied {
+        /// The directory
+        path: std::path::PathBuf,
+    },
+    /// Error when a token passed to [`DevDeviceId::import_token`] is malformed, altered, or signed with
+    /// another key
+    #[error("Invalid device ID token: {0}")]
+    InvalidToken(String),
+}
+
+impl Error {
+    /// A [`Error::StorageError`] labelled with the failing operation (e.g. `create_dir`, `write_file`),
+    /// so logs pinpoint which step failed.
+    pub(crate) fn storage(operation: &str, err: impl std::fmt::Display) -> Self {
+        Error::StorageError(format!("{operation} failed: {err}"))
+    }
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-fn generate_id() -> DevDeviceId {
-    DevDeviceId(Uuid::new_v4())
+/// What to do when storing a device ID while another one is already stored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    /// Fail with [`Error::AlreadySet`] (default)
+    #[default]
+    Error,
+    /// Replace the stored ID
+    Overwrite,
+    /// Keep the stored ID and return it instead
+    KeepExisting,
+}
+
+/// Whose device ID is stored: the current user's, or one shared by all users of the machine.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Scope {
+    /// The current user's device ID, e.g. under `HKEY_CURRENT_USER` (default)
+    #[default]
+    User,
+    /// The machine-wide device ID under `HKEY_LOCAL_MACHINE`, which usually requires elevation to store
+    Machine,
+}
+
+/// An XDG base directory holding the device ID on Unix, see [`DevDeviceIdBuilder::base_dir`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BaseDir {
+    /// `$XDG_CACHE_HOME`, or `$HOME/.cache`: the default on Linux
+    Cache,
+    /// `$XDG_STATE_HOME`, or `$HOME/.local/state`: the default on Unix systems other than Linux and macOS
+    State,
+    /// `$XDG_CONFIG_HOME`, or `$HOME/.config`, which dotfile backups usually include
+    Config,
+    /// `$XDG_DATA_HOME`, or `$HOME/.local/share`
+    Data,
+}
+
+/// Whether the device ID stored by .NET tooling under `~/.microsoft/developer/` is used when none is
+/// stored in the default location, see [`DevDeviceIdBuilder::dotnet_interop`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum DotnetInterop {
+    /// Only read the default location (default)
+    #[default]
+    Ignore,
+    /// Return the .NET tooling ID, leaving the default location empty
+    Adopt,
+    /// Return the .NET tooling ID, and store it in the default location from
+    /// [`DevDeviceId::get_or_generate`], where it is found from then on
+    Copy,
+}
+
+/// What [`DevDeviceId::provision`] did, for provisioning reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ProvisionOutcome {
+    /// The device ID in storage
+    pub id: DevDeviceId,
+    /// Whether the ID was generated and stored by this call, rather than already stored
+    pub generated: bool,
+    /// Where the ID is stored
+    pub location: StorageLocation,
+    /// How long reading, and if needed generating and storing, the ID took
+    pub elapsed: std::time::Duration,
+}
+
+/// How the device ID from the environment compares to the stored one, from [`DevDeviceId::reconcile`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reconciliation {
+    /// Both are set to this ID
+    Agree(DevDeviceId),
+    /// Both are set, to different IDs; the environment takes precedence in [`DevDeviceId::from_env_or_generate`]
+    Differ {
+        /// The ID from the environment variable
+        env: DevDeviceId,
+        /// The ID in storage
+        stored: DevDeviceId,
+    },
+    /// Only the environment variable is set
+    OnlyEnv(DevDeviceId),
+    /// Only storage holds an ID
+    OnlyStored(DevDeviceId),
+    /// Neither is set
+    Neither,
+}
+
+impl Reconciliation {
+    pub(crate) fn of(env: Option<DevDeviceId>, stored: Option<DevDeviceId>) -> Self {
+        match (env, stored) {
+            (Some(env), Some(stored)) if env == stored => Reconciliation::Agree(env),
+            (Some(env), Some(stored)) => Reconciliation::Differ { env, stored },
+            (Some(env), None) => Reconciliation::OnlyEnv(env),
+            (None, Some(stored)) => Reconciliation::OnlyStored(stored),
+            (None, None) => Reconciliation::Neither,
+        }
+    }
+}
+
+/// Where the device ID returned by [`DevDeviceId::resolve`] came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Provenance {
+    /// It was already stored, possibly by another writer that stored it first
+    Retrieved,
+    /// It was generated and stored by this call
+    Generated,
+}
+
+/// A place to look for the device ID, for [`DevDeviceId::resolve_with`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdSource {
+    /// The environment variable with this name, skipped if unset
+    Env(String),
+    /// The file at this path, e.g. in the project directory, skipped if absent
+    File(PathBuf),
+    /// The default storage, skipped if no ID is stored
+    Default,
+}
+
+/// How a new device ID is generated when none is stored.
+#[derive(Clone, Default)]
+pub enum GenerationStrategy {
+    /// A random version 4 UUID (default)
+    #[default]
+    RandomV4,
+    /// A version 5 UUID derived from `seed` (e.g. a hardware serial number) within `namespace`, using SHA-1,
+    /// so the same machine always generates the same ID
+    Sha1Namespace {
+        /// Namespace of the derived UUID
+        namespace: Uuid,
+        /// Machine-specific input to derive the UUID from
+        seed: Vec<u8>,
+    },
+    /// A caller-provided generator
+    Custom(std::sync::Arc<dyn Fn() -> DevDeviceId + Send + Sync>),
+    /// A time-ordered version 7 UUID, embedding the time of generation from the
+    /// [`DevDeviceIdBuilder::clock`], see [`DevDeviceId::generate_v7`]
+    TimeOrderedV7,
+}
+
+impl GenerationStrategy {
+    pub(crate) fn generate(&self, clock: &dyn Clock) -> DevDeviceId {
+        match self {
+            GenerationStrategy::RandomV4 => DevDeviceId::generate(),
+            GenerationStrategy::TimeOrderedV7 => DevDeviceId::generate_v7(clock),
+            GenerationStrategy::Sha1Namespace { namespace, seed } => {
+                DevDeviceId(Uuid::new_v5(namespace, seed))
+            }
+            GenerationStrategy::Custom(generate) => generate(),
+        }
+    }
+}
+
+impl std::fmt::Debug for GenerationStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GenerationStrategy::RandomV4 => f.write_str("RandomV4"),
+            GenerationStrategy::Sha1Namespace { namespace, seed } => f
+                .debug_struct("Sha1Namespace")
+                .field("namespace", namespace)
+                .field("seed", seed)
+                .finish(),
+            GenerationStrategy::Custom(_) => f.write_str("Custom(..)"),
+            GenerationStrategy::TimeOrderedV7 => f.write_str("TimeOrderedV7"),
+        }
+    }
+}
+
+/// The textual form used when writing the device ID to storage.
+///
+/// Reading accepts any form regardless of this setting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StoredFormat {
+    /// Lowercase hyphenated form, e.g. `550e8400-e29b-41d4-a716-446655440000` (default)
+    #[default]
+    Hyphenated,
+    /// Lowercase simple (32-char) form without hyphens, e.g. `550e8400e29b41d4a716446655440000`
+    Simple,
+    /// Lowercase hyphenated form followed by the CRC-32 of the UUID bytes, e.g.
+    /// `550e8400-e29b-41d4-a716-446655440000:e261e61d`, so silent corruption is detected on read
+    Checked,
+}
+
+impl StoredFormat {
+    pub(crate) fn encode(self, id: &DevDeviceId) -> String {
+        let mut encoded = match self {
+            StoredFormat::Hyphenated => id.to_string(),
+            StoredFormat::Simple => format!("{:x}", id.0.simple()),
+            StoredFormat::Checked => format!("{id}:{:08x}", crc32(id.0.as_bytes())),
+        };
+        // other tools compare the stored form as text, so it stays lowercase whatever `Display` does
+        encoded.make_ascii_lowercase();
+        encoded
+    }
+
+    /// Parses a stored value in any format, failing with [`Error::BadUuidFormat`] if the checksum of a
+    /// [`StoredFormat::Checked`] value does not match.
+    pub(crate) fn decode(s: &str) -> Result<DevDeviceId> {
+        let (text, checksum) = match s.split_once(':') {
+            Some((text, checksum)) => (text, Some(checksum)),
+            None => (s, None),
+        };
+        let uuid = Uuid::try_parse(text).map_err(|e| Error::BadUuidFormat(e.to_string()))?;
+        if let Some(checksum) = checksum {
+            let expected = format!("{:08x}", crc32(uuid.as_bytes()));
+            if !checksum.eq_ignore_ascii_case(&expected) {
+                return Err(Error::BadUuidFormat(format!(
+                    "stored checksum {checksum:?} does not match {expected}, the stored device ID may be corrupt"
+                )));
+            }
+        }
+        Ok(DevDeviceId(uuid))
+    }
+
+    /// Parses `trimmed`, the stored value of `read_len` bytes without surrounding whitespace, adding both
+    /// lengths to a [`Error::BadUuidFormat`] so that a truncated value is obvious from the message alone,
+    /// e.g. `(read 36 bytes, 35 after trimming)`. With the `log` feature, the failure is also logged.
+    pub(crate) fn decode_stored(trimmed: &str, read_len: usize) -> Result<DevDeviceId> {
+        StoredFormat::decode(trimmed).map_err(|err| match err {
+            Error::BadUuidFormat(msg) => {
+                let trimming = if trimmed.len() == read_len {
+                    "nothing trimmed".to_string()
+                } else {
+                    format!("{} after trimming", trimmed.len())
+                };
+                let msg = format!("{msg} (read {read_len} bytes, {trimming})");
+                #[cfg(feature = "log")]
+                log::debug!("stored device ID failed to parse: {msg}");
+                Error::BadUuidFormat(msg)
+            }
+            err => err,
+        })
+    }
+}
+
+/// CRC-32 (IEEE, as used by zlib) of `data`.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
 }
 
 impl DevDeviceId {
+    /// Generates a new random (v4) device ID.
+    ///
+    /// This does not read or write storage: the returned ID is not persisted anywhere.
+    /// Use [`DevDeviceId::get_or_generate`] to obtain the device ID for this machine.
+    pub fn generate() -> Self {
+        DevDeviceId(Uuid::new_v4())
+    }
+
+    /// Generates a new time-ordered (v7) device ID: the current time of `clock` in milliseconds, followed by
+    /// random bits. Nothing is stored.
+    ///
+    /// Pass [`SystemClock`] for the real time, or a fixed clock for deterministic timestamps in tests.
+    /// A time before the Unix epoch is embedded as the epoch.
+    pub fn generate_v7(clock: &dyn Clock) -> Self {
+        let since_epoch = clock
+            .now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        let timestamp = uuid::Timestamp::from_unix(
+            uuid::NoContext,
+            since_epoch.as_secs(),
+            since_epoch.subsec_nanos(),
+        );
+        DevDeviceId(Uuid::new_v7(timestamp))
+    }
+
+    /// Generates `n` distinct random (v4) device IDs, e.g. for test fixtures. Nothing is stored.
+    pub fn generate_batch(n: usize) -> Vec<Self> {
+        let mut seen = std::collections::HashSet::with_capacity(n);
+        let mut ids = Vec::with_capacity(n);
+        while ids.len() < n {
+            let id = Self::generate();
+            if seen.insert(id.clone()) {
+                ids.push(id);
+            }
+        }
+        ids
+    }
+
+    /// Creates a device ID with exactly the given bits, e.g. to control the UUID version and variant.
+    /// Nothing is stored.
+    pub const fn from_u128(value: u128) -> Self {
+        DevDeviceId(Uuid::from_u128(value))
+    }
+
+    /// Creates a device ID from the UUID built by `builder`, e.g. with specific version and variant bits.
+    /// Nothing is stored.
+    pub fn from_builder(builder: uuid::Builder) -> Self {
+        DevDeviceId(builder.into_uuid())
+    }
+
+    /// Parses a device ID in any form the `uuid` crate recognizes, in either case and ignoring
+    /// surrounding whitespace: hyphenated, simple (32 hex digits), braced (`{...}`) or URN
+    /// (`urn:uuid:...`). Use this for IDs from other sources, e.g. headers or other tools.
+    ///
+    /// Parsing with [`str::parse`] instead accepts only the canonical form that this crate writes.
+    pub fn parse_lenient(s: &str) -> Result<Self> {
+        let uuid = Uuid::try_parse(s.trim()).map_err(|e| Error::BadUuidFormat(e.to_string()))?;
+        Ok(DevDeviceId(uuid))
+    }
+
+    /// Returns `true` if `s` is this device ID in any form accepted by [`DevDeviceId::parse_lenient`],
+    /// e.g. uppercase or braced, and `false` if it is another ID or not an ID at all.
+    pub fn eq_str(&self, s: &str) -> bool {
+        Self::parse_lenient(s).is_ok_and(|other| other == *self)
+    }
+
+    /// Returns a builder to customize how the device ID is stored and retrieved.
+    pub fn builder() -> DevDeviceIdBuilder {
+        DevDeviceIdBuilder::new()
+    }
+
     /// Retrieves the device ID from storage or generates a new one if it doesn't exist.
     /// If an ID does not exist, a new one is generated and stored.
     /// If the function does not return `Ok(device_id)`, the generated ID was not stored.
+    ///
+    /// Nothing is stored while [`EPHEMERAL_ENV_VAR`] is set to `1`.
     pub fn get_or_generate() -> Result<Self> {
-        match storage::retrieve()? {
-            Some(id) => Ok(id),
-            None => {
-                let id = generate_id();
-                storage::store(&id)?;
-                Ok(storage::retrieve()?.unwrap_or(id))
-            }
-        }
+        DevDeviceIdBuilder::new().get_or_generate()
     }
 
     /// Retrieves the device ID from storage, returning `None` if it does not exist
     /// or an error if there was a problem retrieving it.
     pub fn get() -> Result<Option<Self>> {
-        storage::retrieve()
+        DevDeviceIdBuilder::new().get()
+    }
+
+    /// Like [`DevDeviceId::get`], but if the stored value is not a valid device ID, removes it and returns `None`
+    /// instead of failing with [`Error::BadUuidFormat`], so that [`DevDeviceId::get_or_generate`] can store a new ID.
+    pub fn get_repairing() -> Result<Option<Self>> {
+        DevDeviceIdBuilder::new().get_repairing()
+    }
+
+    /// Returns `true` for the all-zeros nil UUID, a placeholder that should never be used as a device ID.
+    pub fn is_nil(&self) -> bool {
+        self.0.is_nil()
+    }
+
+    /// Stores this ID as the device ID, failing with [`Error::AlreadySet`] if one is already stored.
+    ///
+    /// The nil UUID is rejected with [`Error::InvalidId`]; see [`DevDeviceIdBuilder::allow_nil`].
+    pub fn try_set(&self) -> Result<()> {
+        DevDeviceIdBuilder::new().set(self).map(drop)
+    }
+
+    /// Stores this ID as the device ID, replacing any stored ID.
+    ///
+    /// The nil UUID is rejected with [`Error::InvalidId`]; see [`DevDeviceIdBuilder::allow_nil`].
+    pub fn force_set(&self) -> Result<()> {
+        DevDeviceIdBuilder::new()
+            .overwrite_policy(OverwritePolicy::Overwrite)
+            .set(self)
+            .map(drop)
+    }
+
+    /// Removes the stored device ID, if any, so that the next [`DevDeviceId::get_or_generate`] stores a new one.
+    pub fn reset() -> Result<()> {
+        DevDeviceIdBuilder::new().reset()
+    }
+
+    /// Uses the device ID from the environment variable `var_name` (e.g. `DEVDEVICEID`) if it is set,
+    /// otherwise behaves like [`DevDeviceId::get_or_generate`].
+    ///
+    /// This lets CI pin a reproducible device ID. A set but malformed value is an error rather than ignored.
+    /// The value from the environment is not stored, unless requested with [`DevDeviceIdBuilder::persist_env_id`].
+    pub fn from_env_or_generate(var_name: &str) -> Result<Self> {
+        DevDeviceIdBuilder::new().from_env_or_generate(var_name)
+    }
+
+    /// Checks that [`DevDeviceId::get_or_generate`] would succeed, without storing an ID, e.g. in an
+    /// installer: the storage location is resolved, any stored ID must be readable, and write access
+    /// is probed with a temporary file (Unix) or value (Windows) that is removed again.
+    ///
+    /// Missing directories are not created, but a missing registry key is.
+    pub fn preflight() -> Result<()> {
+        DevDeviceIdBuilder::new().preflight()
+    }
+
+    /// Retrieves the provenance metadata stored alongside the device ID, if any.
+    ///
+    /// Returns `Ok(None)` if no metadata was recorded or it cannot be parsed; see
+    /// [`DevDeviceIdBuilder::record_metadata`].
+    pub fn provenance_metadata() -> Result<Option<Metadata>> {
+        DevDeviceIdBuilder::new().provenance_metadata()
+    }
+
+    /// Lists every known location where a device ID may be stored on this platform, with the result of
+    /// reading it there: the ID, if any, or the error, so that one unreadable location does not hide the
+    /// others. Nothing is modified.
+    ///
+    /// This is a diagnostic for detecting conflicting IDs, e.g. both `XDG_CACHE_HOME` and `~/.cache` on Linux,
+    /// or both the user and machine hives and 32/64-bit views on Windows.
+    pub fn scan_locations() -> Result<Vec<(StorageLocation, Result<Option<Self>>)>> {
+        storage::scan_locations()
+    }
+
+    /// Lists the per-application device IDs of the current user, stored with
+    /// [`DevDeviceIdBuilder::app_name`], by application name: the subdirectories of the default
+    /// location on Unix, or the subkeys of the default key on Windows. Nothing is modified.
+    ///
+    /// Entries holding no ID are left out, and so are malformed ones;
+    /// see [`DevDeviceId::enumerate_apps_with_errors`] to report those.
+    pub fn enumerate_apps() -> Result<Vec<(String, Self)>> {
+        Ok(storage::enumerate_apps()?
+            .into_iter()
+            .filter_map(|(name, id)| Some((name, id.ok()?)))
+            .collect())
+    }
+
+    /// Like [`DevDeviceId::enumerate_apps`], but includes the applications whose ID could not be read,
+    /// with the error.
+    pub fn enumerate_apps_with_errors() -> Result<Vec<(String, Result<Self>)>> {
+        storage::enumerate_apps()
+    }
+
+    /// Reports the kind of storage used by [`DevDeviceId::get_or_generate`] and [`DevDeviceId::get`] on this
+    /// platform. This does no I/O.
+    pub fn storage_kind() -> StorageKind {
+        StorageKind::DEFAULT
+    }
+
+    /// Like [`DevDeviceId::get_or_generate`], but using the file at `path` rather than the default location,
+    /// on any platform. Missing parent directories are created.
+    pub fn get_or_generate_at(path: &Path) -> Result<Self> {
+        DevDeviceIdBuilder::new().get_or_generate_at(path)
+    }
+
+    /// Like [`DevDeviceId::get`], but using the file at `path` rather than the default location, on any platform.
+    pub fn get_at(path: &Path) -> Result<Option<Self>> {
+        DevDeviceIdBuilder::new().get_at(path)
+    }
+
+    /// Returns the device ID from the first of `sources` that has one, in order, or `None` if none has.
+    /// Nothing is generated or stored.
+    ///
+    /// A source holding a malformed ID is an error rather than skipped, so that a broken override
+    /// is noticed instead of silently falling through to a lower-priority source.
+    pub fn resolve_with(sources: &[IdSource]) -> Result<Option<Self>> {
+        DevDeviceIdBuilder::new().resolve_with(sources)
+    }
+
+    /// Watches the stored device ID for changes by any process, e.g. an external reset; see [`DeviceIdWatcher`].
+    #[cfg(feature = "watch")]
+    pub fn watch() -> Result<DeviceIdWatcher> {
+        DevDeviceIdBuilder::new().watch()
+    }
+
+    /// Seals the stored device ID against accidental overwrite or reset, e.g. once it is provisioned in an image:
+    /// overwriting it (e.g. with [`DevDeviceId::force_set`]) or removing it (e.g. with [`DevDeviceId::reset`])
+    /// then fails with [`Error::Sealed`]. Fails if no ID is stored.
+    ///
+    /// On Unix a `deviceid.sealed` marker file is written next to the ID and the file is made read-only;
+    /// only the marker counts, so a file that is read-only for another reason is not sealed. On Windows
+    /// a `deviceid_sealed` marker value is written next to the ID, rather than denying writes with an
+    /// ACL, because the key is shared with other tools. This guards against mistakes, not a determined
+    /// user: removing the marker unseals the ID. The immutable attribute, which needs root, is not set.
+    pub fn seal() -> Result<()> {
+        DevDeviceIdBuilder::new().seal()
+    }
+
+    /// Undoes [`DevDeviceId::seal`], so that the device ID can be overwritten or reset again.
+    /// Does nothing if it is not sealed.
+    pub fn unseal() -> Result<()> {
+        DevDeviceIdBuilder::new().unseal()
+    }
+
+    /// Reports whether the stored device ID is sealed with [`DevDeviceId::seal`].
+    pub fn is_sealed() -> Result<bool> {
+        DevDeviceIdBuilder::new().is_sealed()
+    }
+
+    /// Reports whether the stored device ID equals `expected`, e.g. for a health check that pins the
+    /// expected ID to detect tampering. Returns `Ok(false)` if no ID is stored. Nothing is modified.
+    ///
+    /// A stored value that is not a valid device ID is an error, as with [`DevDeviceId::get`].
+    pub fn verify_matches(expected: &DevDeviceId) -> Result<bool> {
+        DevDeviceIdBuilder::new().verify_matches(expected)
+    }
+
+    /// Compares the device ID from the environment variable `var_name` (e.g. `DEVDEVICEID`) to the stored one,
+    /// to diagnose a mismatch that [`DevDeviceId::from_env_or_generate`] silently resolves in favor of the
+    /// environment. Nothing is stored.
+    ///
+    /// A set but malformed value, in the environment or in storage, is an error.
+    pub fn reconcile(var_name: &str) -> Result<Reconciliation> {
+        DevDeviceIdBuilder::new().reconcile(var_name)
+    }
+
+    /// Like [`DevDeviceId::get_or_generate`], but reports whether the ID was generated, where it is stored,
+    /// and how long it took, e.g. for installers and CI.
+    pub fn provision() -> Result<ProvisionOutcome> {
+        DevDeviceIdBuilder::new().provision()
+    }
+
+    /// Like [`DevDeviceId::get_or_generate`], but using the given storage backend.
+    pub fn get_or_generate_in(storage: &mut impl Storage) -> Result<Self> {
+        DevDeviceIdBuilder::new().get_or_generate_in(storage)
+    }
+
+    /// Retrieves the device ID from `storage`, or stores one from `generate` if none is stored, reporting
+    /// which happened. This is the core of [`DevDeviceId::get_or_generate`] without anything implicit:
+    /// no environment variables, clock or builder options are consulted, so it is deterministic given a
+    /// deterministic `storage` and `generate`, e.g. [`MemoryStorage`] and a fixed ID.
+    ///
+    /// `generate` is only called if no ID is stored. The new ID is stored with [`ensure_stored`], so if
+    /// another writer stored an ID first, that one is returned as [`Provenance::Retrieved`].
+    pub fn resolve(
+        storage: &mut impl Storage,
+        generate: impl FnOnce() -> DevDeviceId,
+    ) -> Result<(Self, Provenance)> {
+        Self::try_resolve(storage, || Ok(generate()))
+    }
+
+    /// Like [`DevDeviceId::resolve`], but `generate` may reject the ID before it is stored.
+    pub(crate) fn try_resolve(
+        storage: &mut impl Storage,
+        generate: impl FnOnce() -> Result<DevDeviceId>,
+    ) -> Result<(Self, Provenance)> {
+        if let Some(id) = storage.retrieve()? {
+            return Ok((id, Provenance::Retrieved));
+        }
+        let id = generate()?;
+        let stored = ensure_stored(storage, id.clone())?;
+        let provenance = if stored == id {
+            Provenance::Generated
+        } else {
+            Provenance::Retrieved
+        };
+        Ok((stored, provenance))
+    }
+
+    /// Like [`DevDeviceId::get`], but using the given storage backend.
+    pub fn get_in(storage: &mut impl Storage) -> Result<Option<Self>> {
+        storage.retrieve()
+    }
+
+    /// Like [`DevDeviceId::get_repairing`], but using the given storage backend.
+    ///
+    /// A corrupt value is left in place if the storage is read-only.
+    pub fn get_repairing_in(storage: &mut impl Storage) -> Result<Option<Self>> {
+        match storage.retrieve() {
+            Err(Error::BadUuidFormat(_)) => match storage.clear() {
+                Ok(()) | Err(Error::ReadOnly) => Ok(None),
+                Err(err) => Err(err),
+            },
+            result => result,
+        }
+    }
+
+    /// Like [`DevDeviceId::reconcile`], but using the given storage backend.
+    pub fn reconcile_in(var_name: &str, storage: &mut impl Storage) -> Result<Reconciliation> {
+        DevDeviceIdBuilder::new().reconcile_in(var_name, storage)
+    }
+
+    /// Like [`DevDeviceId::verify_matches`], but using the given storage backend.
+    pub fn verify_matches_in(storage: &mut impl Storage, expected: &DevDeviceId) -> Result<bool> {
+        Ok(storage.retrieve()?.as_ref() == Some(expected))
     }
 }
 
@@ -85,6 +795,68 @@ impl std::fmt::Display for DevDeviceId {
     }
 }
 
+/// Parses the canonical form written by [`DevDeviceId`]'s `Display`: lowercase and hyphenated,
+/// e.g. `550e8400-e29b-41d4-a716-446655440000`. Any other form fails with [`Error::BadUuidFormat`];
+/// see [`DevDeviceId::parse_lenient`] to accept them.
+impl std::str::FromStr for DevDeviceId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let id = DevDeviceId(Uuid::try_parse(s).map_err(|e| Error::BadUuidFormat(e.to_string()))?);
+        if id.to_string() != s {
+            return Err(Error::BadUuidFormat(
+                "expected a lowercase hyphenated UUID".to_string(),
+            ));
+        }
+        Ok(id)
+    }
+}
+
+/// The canonical form written by `Display`, e.g. `550e8400-e29b-41d4-a716-446655440000`.
+impl From<DevDeviceId> for String {
+    fn from(id: DevDeviceId) -> Self {
+        id.to_string()
+    }
+}
+
+/// Accepts either the 16 raw bytes of the UUID, in big-endian order, or its ASCII text in any form
+/// [`DevDeviceId::parse_lenient`] accepts. Anything else fails with [`Error::BadUuidFormat`].
+impl TryFrom<&[u8]> for DevDeviceId {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self> {
+        let uuid = match <[u8; 16]>::try_from(bytes) {
+            Ok(raw) => Uuid::from_bytes(raw),
+            Err(_) => Uuid::try_parse_ascii(bytes.trim_ascii())
+                .map_err(|e| Error::BadUuidFormat(e.to_string()))?,
+        };
+        Ok(DevDeviceId(uuid))
+    }
+}
+
+/// Like the conversion from `&[u8]`: the 16 raw bytes, or ASCII UUID text.
+impl TryFrom<Vec<u8>> for DevDeviceId {
+    type Error = Error;
+
+    fn try_from(bytes: Vec<u8>) -> Result<Self> {
+        Self::try_from(bytes.as_slice())
+    }
+}
+
+impl AsRef<Uuid> for DevDeviceId {
+    fn as_ref(&self) -> &Uuid {
+        &self.0
+    }
+}
+
+/// `DevDeviceId` hashes and compares exactly like its [`Uuid`], so collections keyed by `DevDeviceId` can be
+/// queried with a `&Uuid`.
+impl std::borrow::Borrow<Uuid> for DevDeviceId {
+    fn borrow(&self) -> &Uuid {
+        &self.0
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -101,6 +873,241 @@ mod tests {
         assert_eq!(formatted, expected);
     }
 
+    #[test]
+    fn test_stored_format_encode() {
+        let id = DevDeviceId(Uuid::from_u128(0x550e8400_e29b_41d4_a716_446655440000));
+        assert_eq!(
+            StoredFormat::Hyphenated.encode(&id),
+            "550e8400-e29b-41d4-a716-446655440000"
+        );
+        assert_eq!(
+            StoredFormat::Simple.encode(&id),
+            "550e8400e29b41d4a716446655440000"
+        );
+        assert_eq!(
+            StoredFormat::Checked.encode(&id),
+            "550e8400-e29b-41d4-a716-446655440000:e261e61d"
+        );
+    }
+
+    #[test]
+    fn test_reconciliation() {
+        let id = DevDeviceId::generate();
+        let other = DevDeviceId::generate();
+        let cases = [
+            (Some(&id), Some(&id), Reconciliation::Agree(id.clone())),
+            (
+                Some(&id),
+                Some(&other),
+                Reconciliation::Differ {
+                    env: id.clone(),
+                    stored: other.clone(),
+                },
+            ),
+            (Some(&id), None, Reconciliation::OnlyEnv(id.clone())),
+            (
+                None,
+                Some(&other),
+                Reconciliation::OnlyStored(other.clone()),
+            ),
+            (None, None, Reconciliation::Neither),
+        ];
+        for (env, stored, expected) in cases {
+            assert_eq!(Reconciliation::of(env.cloned(), stored.cloned()), expected);
+        }
+    }
+
+    #[test]
+    fn test_verify_matches_in() {
+        let id = DevDeviceId::generate();
+        let mut storage = MemoryStorage::with_id(id.clone());
+        assert!(DevDeviceId::verify_matches_in(&mut storage, &id).unwrap());
+        assert!(!DevDeviceId::verify_matches_in(&mut storage, &DevDeviceId::generate()).unwrap());
+        assert_eq!(storage.retrieve().unwrap(), Some(id.clone()));
+
+        let mut storage = MemoryStorage::new();
+        assert!(!DevDeviceId::verify_matches_in(&mut storage, &id).unwrap());
+        assert_eq!(storage.retrieve().unwrap(), None);
+    }
+
+    #[test]
+    fn test_resolve() {
+        let fixed = DevDeviceId(Uuid::from_u128(0x550e8400_e29b_41d4_a716_446655440000));
+        let mut storage = MemoryStorage::new();
+        let (id, provenance) = DevDeviceId::resolve(&mut storage, || fixed.clone()).unwrap();
+        assert_eq!((id, provenance), (fixed.clone(), Provenance::Generated));
+        assert_eq!(storage.retrieve().unwrap(), Some(fixed.clone()));
+
+        let (id, provenance) =
+            DevDeviceId::resolve(&mut storage, || unreachable!("an ID is stored")).unwrap();
+        assert_eq!((id, provenance), (fixed, Provenance::Retrieved));
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_stored_format_checked_roundtrip() {
+        let id = DevDeviceId::generate();
+        let encoded = StoredFormat::Checked.encode(&id);
+        assert_eq!(encoded.len(), 36 + 1 + 8);
+        assert_eq!(StoredFormat::decode(&encoded).unwrap(), id);
+        // the plain formats are still detected
+        assert_eq!(StoredFormat::decode(&id.to_string()).unwrap(), id);
+    }
+
+    #[test]
+    fn test_stored_format_checked_tampered() {
+        let encoded = StoredFormat::Checked.encode(&DevDeviceId::from_u128(1));
+        let tampered = encoded.replacen("00000000-", "00000001-", 1);
+        assert!(matches!(
+            StoredFormat::decode(&tampered),
+            Err(Error::BadUuidFormat(msg)) if msg.contains("checksum")
+        ));
+        let (id, _) = encoded.split_once(':').unwrap();
+        assert!(matches!(
+            StoredFormat::decode(&format!("{id}:00000000")),
+            Err(Error::BadUuidFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_from_builder_keeps_bits() {
+        let builder = uuid::Builder::from_random_bytes([0xab; 16])
+            .with_variant(uuid::Variant::Microsoft)
+            .with_version(uuid::Version::Custom);
+        let id = DevDeviceId::from_builder(builder);
+        assert_eq!(id.0.get_variant(), uuid::Variant::Microsoft);
+        assert_eq!(id.0.get_version_num(), 8);
+        assert_eq!(id.to_string().parse::<DevDeviceId>().unwrap(), id);
+
+        let id = DevDeviceId::from_u128(0x550e8400_e29b_41d4_a716_446655440000);
+        assert_eq!(id.to_string(), "550e8400-e29b-41d4-a716-446655440000");
+        assert_eq!(id.to_string().parse::<DevDeviceId>().unwrap(), id);
+    }
+
+    #[test]
+    fn test_string_and_bytes_conversions() {
+        let id = DevDeviceId::from_u128(0x550e8400_e29b_41d4_a716_446655440000);
+        assert_eq!(
+            String::from(id.clone()),
+            "550e8400-e29b-41d4-a716-446655440000"
+        );
+
+        let raw = 0x550e8400_e29b_41d4_a716_446655440000u128.to_be_bytes();
+        assert_eq!(DevDeviceId::try_from(&raw[..]).unwrap(), id);
+        assert_eq!(DevDeviceId::try_from(raw.to_vec()).unwrap(), id);
+        for text in [
+            "550e8400-e29b-41d4-a716-446655440000",
+            "550E8400E29B41D4A716446655440000",
+        ] {
+            assert_eq!(DevDeviceId::try_from(text.as_bytes()).unwrap(), id);
+            assert_eq!(DevDeviceId::try_from(text.as_bytes().to_vec()).unwrap(), id);
+        }
+
+        for bytes in [&raw[..15], &[0u8; 17][..], b"not-a-uuid", b"\xff\xfe"] {
+            assert!(matches!(
+                DevDeviceId::try_from(bytes),
+                Err(Error::BadUuidFormat(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_from_str_is_strict() {
+        let id: DevDeviceId = "550e8400-e29b-41d4-a716-446655440000".parse().unwrap();
+        assert_eq!(
+            id,
+            DevDeviceId::from_u128(0x550e8400_e29b_41d4_a716_446655440000)
+        );
+        for input in [
+            "550E8400-E29B-41D4-A716-446655440000",
+            "550e8400e29b41d4a716446655440000",
+            "{550e8400-e29b-41d4-a716-446655440000}",
+            "urn:uuid:550e8400-e29b-41d4-a716-446655440000",
+            " 550e8400-e29b-41d4-a716-446655440000",
+            "not-a-uuid",
+        ] {
+            assert!(
+                matches!(input.parse::<DevDeviceId>(), Err(Error::BadUuidFormat(_))),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_lenient_forms() {
+        let id = DevDeviceId::from_u128(0x550e8400_e29b_41d4_a716_446655440000);
+        for input in [
+            "550e8400-e29b-41d4-a716-446655440000",
+            "550E8400-E29B-41D4-A716-446655440000",
+            "550e8400e29b41d4a716446655440000",
+            "550E8400E29B41D4A716446655440000",
+            "{550e8400-e29b-41d4-a716-446655440000}",
+            "{550E8400-E29B-41D4-A716-446655440000}",
+            "urn:uuid:550e8400-e29b-41d4-a716-446655440000",
+            " 550e8400-e29b-41d4-a716-446655440000\r\n",
+        ] {
+            assert_eq!(DevDeviceId::parse_lenient(input).unwrap(), id, "{input}");
+        }
+        for input in ["", "not-a-uuid", "550e8400-e29b-41d4-a716-44665544000"] {
+            assert!(
+                matches!(
+                    DevDeviceId::parse_lenient(input),
+                    Err(Error::BadUuidFormat(_))
+                ),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_eq_str() {
+        let id = DevDeviceId::from_u128(0x550e8400_e29b_41d4_a716_446655440000);
+        assert!(id.eq_str("550e8400-e29b-41d4-a716-446655440000"));
+        assert!(id.eq_str("550E8400-E29B-41D4-A716-446655440000"));
+        assert!(id.eq_str("{550e8400-e29b-41d4-a716-446655440000}"));
+        assert!(id.eq_str("550e8400e29b41d4a716446655440000"));
+        assert!(!id.eq_str("550e8400-e29b-41d4-a716-446655440001"));
+        assert!(!id.eq_str("garbage"));
+        assert!(!id.eq_str(""));
+    }
+
+    #[test]
+    fn test_ordering_matches_uuid_bytes() {
+        let low = DevDeviceId(Uuid::from_u128(0x0000_0000_0000_4000_8000_0000_0000_00ff));
+        let high = DevDeviceId(Uuid::from_u128(0xff00_0000_0000_4000_8000_0000_0000_0000));
+        assert!(low < high);
+
+        let mut ids: Vec<DevDeviceId> = (0..16).map(|_| DevDeviceId::generate()).collect();
+        ids.sort();
+        assert!(
+            ids.windows(2)
+                .all(|w| w[0].0.as_bytes() <= w[1].0.as_bytes())
+        );
+    }
+
+    #[test]
+    fn test_generate_is_random_v4() {
+        let id = DevDeviceId::generate();
+        let id2 = DevDeviceId::generate();
+        assert_ne!(id, id2);
+        assert_eq!(id.0.get_version_num(), 4);
+    }
+
+    #[test]
+    fn test_generate_batch() {
+        let ids = DevDeviceId::generate_batch(1000);
+        assert_eq!(ids.len(), 1000);
+        let distinct: std::collections::HashSet<_> = ids.iter().collect();
+        assert_eq!(distinct.len(), 1000);
+        assert!(ids.iter().all(|id| id.0.get_version_num() == 4));
+        assert!(DevDeviceId::generate_batch(0).is_empty());
+    }
+
+    #[cfg(any(target_family = "unix", target_family = "windows", target_os = "wasi"))]
     #[test]
     fn test_get_or_generate_idempotent() {
         let id = DevDeviceId::get_or_generate().unwrap();
@@ -109,4 +1116,34 @@ mod tests {
         let id3 = DevDeviceId::get().unwrap().unwrap();
         assert_eq!(id, id3);
     }
+
+    #[test]
+    fn test_storage_kind() {
+        #[cfg(any(target_family = "unix", target_os = "wasi"))]
+        assert_eq!(DevDeviceId::storage_kind(), StorageKind::File);
+        #[cfg(target_family = "windows")]
+        assert_eq!(DevDeviceId::storage_kind(), StorageKind::Registry);
+        #[cfg(not(any(target_family = "unix", target_family = "windows", target_os = "wasi")))]
+        assert_eq!(DevDeviceId::storage_kind(), StorageKind::Unsupported);
+        assert_eq!(StorageKind::Memory.to_string(), "memory");
+    }
+
+    #[test]
+    fn test_storage_constants() {
+        assert_eq!(UNIX_SUBPATH, "Microsoft/DeveloperTools");
+        assert_eq!(WINDOWS_REGISTRY_PATH, r"SOFTWARE\Microsoft\DeveloperTools");
+        assert_eq!(STORAGE_KEY, "deviceid");
+    }
+
+    #[test]
+    fn test_lookup_by_uuid() {
+        use std::collections::HashMap;
+
+        let id = DevDeviceId::generate();
+        let uuid = *id.as_ref();
+        let mut map = HashMap::new();
+        map.insert(id.clone(), "value");
+        assert_eq!(map.get(&uuid), Some(&"value"));
+        assert_eq!(map.get(&Uuid::nil()), None);
+    }
 }
@@ -0,0 +1,142 @@
+use std::path::{Path, PathBuf};
+
+/// A place where a device ID may be stored.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum StorageLocation {
+    /// A file holding the device ID
+    File(PathBuf),
+    /// A registry value holding the device ID
+    Registry {
+        /// Full key path including the hive, e.g. `HKEY_CURRENT_USER\SOFTWARE\Microsoft\DeveloperTools`
+        key: String,
+        /// Name of the value holding the device ID
+        value: String,
+        /// Whether the 32-bit registry view is used rather than the 64-bit one
+        view_32bit: bool,
+    },
+}
+
+impl StorageLocation {
+    /// Renders the location for logs, e.g. `~/.cache/Microsoft/DeveloperTools/deviceid` or
+    /// `HKEY_CURRENT_USER\SOFTWARE\Microsoft\DeveloperTools\deviceid (32-bit view)`.
+    ///
+    /// With `redact_home`, a file path under the user's home directory (`$HOME`, or `%USERPROFILE%` on
+    /// Windows) starts with `~` instead, and the user SID in a key under `HKEY_USERS` is replaced by
+    /// `<user>`, so the location can be logged where user names are sensitive.
+    pub fn display(&self, redact_home: bool) -> String {
+        let home = redact_home.then(crate::storage::home_dir).flatten();
+        self.render(redact_home, home.as_deref())
+    }
+
+    fn render(&self, redact_home: bool, home: Option<&Path>) -> String {
+        match self {
+            StorageLocation::File(path) => {
+                match home.and_then(|home| path.strip_prefix(home).ok()) {
+                    Some(rest) if rest.as_os_str().is_empty() => "~".to_string(),
+                    Some(rest) => format!("~{}{}", std::path::MAIN_SEPARATOR, rest.display()),
+                    None => path.display().to_string(),
+                }
+            }
+            StorageLocation::Registry {
+                key,
+                value,
+                view_32bit,
+            } => {
+                let key = if redact_home {
+                    redact_sid(key)
+                } else {
+                    key.clone()
+                };
+                let view = if *view_32bit { " (32-bit view)" } else { "" };
+                format!(r"{key}\{value}{view}")
+            }
+        }
+    }
+}
+
+/// Replaces the user SID in `HKEY_USERS\<SID>\...`, e.g. `S-1-5-21-...`, with `<user>`.
+fn redact_sid(key: &str) -> String {
+    let mut segments: Vec<&str> = key.split('\\').collect();
+    if segments.len() > 1
+        && segments[0].eq_ignore_ascii_case("HKEY_USERS")
+        && segments[1].to_ascii_uppercase().starts_with("S-1-")
+    {
+        segments[1] = "<user>";
+    }
+    segments.join("\\")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry(key: &str, view_32bit: bool) -> StorageLocation {
+        StorageLocation::Registry {
+            key: key.to_string(),
+            value: "deviceid".to_string(),
+            view_32bit,
+        }
+    }
+
+    #[cfg(not(target_family = "windows"))]
+    #[test]
+    fn test_display_file() {
+        let home = Path::new("/home/user");
+        let location = StorageLocation::File("/home/user/.cache/deviceid".into());
+        assert_eq!(location.render(false, None), "/home/user/.cache/deviceid");
+        assert_eq!(location.render(true, Some(home)), "~/.cache/deviceid");
+        assert_eq!(
+            StorageLocation::File(home.into()).render(true, Some(home)),
+            "~"
+        );
+
+        // only whole components are redacted
+        let location = StorageLocation::File("/home/username/deviceid".into());
+        assert_eq!(location.render(true, Some(home)), "/home/username/deviceid");
+        assert_eq!(location.render(true, None), "/home/username/deviceid");
+    }
+
+    #[cfg(target_family = "windows")]
+    #[test]
+    fn test_display_file() {
+        let home = Path::new(r"C:\Users\user");
+        let location = StorageLocation::File(r"C:\Users\user\AppData\deviceid".into());
+        assert_eq!(
+            location.render(false, None),
+            r"C:\Users\user\AppData\deviceid"
+        );
+        assert_eq!(location.render(true, Some(home)), r"~\AppData\deviceid");
+
+        let location = StorageLocation::File(r"D:\deviceid".into());
+        assert_eq!(location.render(true, Some(home)), r"D:\deviceid");
+    }
+
+    #[test]
+    fn test_display_registry() {
+        let location = registry(
+            r"HKEY_CURRENT_USER\SOFTWARE\Microsoft\DeveloperTools",
+            false,
+        );
+        let expected = r"HKEY_CURRENT_USER\SOFTWARE\Microsoft\DeveloperTools\deviceid";
+        assert_eq!(location.render(false, None), expected);
+        assert_eq!(location.render(true, None), expected);
+
+        let location = registry(
+            r"HKEY_USERS\S-1-5-21-1004336348-1177238915-682003330-512\SOFTWARE",
+            true,
+        );
+        assert_eq!(
+            location.render(false, None),
+            r"HKEY_USERS\S-1-5-21-1004336348-1177238915-682003330-512\SOFTWARE\deviceid (32-bit view)"
+        );
+        assert_eq!(
+            location.render(true, None),
+            r"HKEY_USERS\<user>\SOFTWARE\deviceid (32-bit view)"
+        );
+        let location = registry(r"HKEY_USERS\.DEFAULT\SOFTWARE", false);
+        assert_eq!(
+            location.render(true, None),
+            r"HKEY_USERS\.DEFAULT\SOFTWARE\deviceid"
+        );
+    }
+}
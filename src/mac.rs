@@ -0,0 +1,97 @@
+#![cfg(feature = "mac-fallback")]
+
+use crate::{DevDeviceId, Result, storage};
+use uuid::Uuid;
+
+/// Namespace of the v5 UUIDs derived from MAC addresses by [`DevDeviceId::from_mac_fallback`].
+const MAC_NAMESPACE: Uuid = Uuid::from_u128(0x3a9a8db6_b214_4a63_acc0_9e243566b442);
+
+impl DevDeviceId {
+    /// Returns the device ID like [`DevDeviceId::get_or_generate`], or, if storage is unavailable, one
+    /// derived from the first stable MAC address: a v5 UUID that is the same on every call, and is not stored.
+    ///
+    /// A stored ID always takes precedence. The fallback is logged as a warning through the `log` crate.
+    /// Only globally administered unicast addresses count as stable, since locally administered ones are often
+    /// randomized. MAC addresses are currently only read on Linux, from `/sys/class/net`; without one, the
+    /// storage error is returned.
+    pub fn from_mac_fallback() -> Result<Self> {
+        from_mac_fallback_with(DevDeviceId::get_or_generate(), storage::mac_address)
+    }
+}
+
+fn from_mac_fallback_with(
+    stored: Result<DevDeviceId>,
+    mac_address: impl FnOnce() -> Option<[u8; 6]>,
+) -> Result<DevDeviceId> {
+    let err = match stored {
+        Ok(id) => return Ok(id),
+        Err(err) => err,
+    };
+    let Some(mac) = mac_address() else {
+        return Err(err);
+    };
+    log::warn!("device ID storage is unavailable ({err}), using an ID derived from a MAC address");
+    Ok(DevDeviceId(Uuid::new_v5(&MAC_NAMESPACE, &mac)))
+}
+
+/// Parses a colon-separated MAC address, e.g. `00:1a:2b:3c:4d:5e`, if it is stable: globally administered,
+/// unicast, and not all zeros.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub(crate) fn parse_stable_mac(s: &str) -> Option<[u8; 6]> {
+    let mut mac = [0u8; 6];
+    let mut octets = s.trim().split(':');
+    for byte in &mut mac {
+        let octet = octets.next().filter(|octet| octet.len() == 2)?;
+        *byte = u8::from_str_radix(octet, 16).ok()?;
+    }
+    let stable = octets.next().is_none() && mac != [0; 6] && mac[0] & 0b11 == 0;
+    stable.then_some(mac)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    const MAC: [u8; 6] = [0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e];
+
+    fn unavailable() -> Result<DevDeviceId> {
+        Err(Error::StorageError("read-only filesystem".to_string()))
+    }
+
+    #[test]
+    fn test_mac_fallback_is_deterministic() {
+        let first = from_mac_fallback_with(unavailable(), || Some(MAC)).unwrap();
+        let second = from_mac_fallback_with(unavailable(), || Some(MAC)).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(first.0.get_version_num(), 5);
+        let other =
+            from_mac_fallback_with(unavailable(), || Some([0x00, 0x1a, 0, 0, 0, 1])).unwrap();
+        assert_ne!(first, other);
+    }
+
+    #[test]
+    fn test_mac_fallback_prefers_stored_id() {
+        let stored = DevDeviceId::generate();
+        let id = from_mac_fallback_with(Ok(stored.clone()), || panic!("MAC read")).unwrap();
+        assert_eq!(id, stored);
+    }
+
+    #[test]
+    fn test_mac_fallback_without_mac() {
+        let result = from_mac_fallback_with(unavailable(), || None);
+        assert!(matches!(result, Err(Error::StorageError(_))));
+    }
+
+    #[test]
+    fn test_parse_stable_mac() {
+        assert_eq!(parse_stable_mac("00:1a:2b:3c:4d:5e\n"), Some(MAC));
+        assert_eq!(parse_stable_mac("00:00:00:00:00:00"), None);
+        // locally administered (randomized) and multicast
+        assert_eq!(parse_stable_mac("02:1a:2b:3c:4d:5e"), None);
+        assert_eq!(parse_stable_mac("01:1a:2b:3c:4d:5e"), None);
+        assert_eq!(parse_stable_mac("00:1a:2b:3c:4d"), None);
+        assert_eq!(parse_stable_mac("00:1a:2b:3c:4d:5e:6f"), None);
+        assert_eq!(parse_stable_mac("not a mac"), None);
+    }
+}
@@ -0,0 +1,101 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Provenance of a stored device ID: which crate version wrote it, and when.
+///
+/// Only recorded when enabled with [`crate::DevDeviceIdBuilder::record_metadata`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    /// Version of this crate that stored the device ID
+    pub version: String,
+    /// When the device ID was stored, in seconds since the Unix epoch
+    pub written_at: u64,
+}
+
+impl Metadata {
+    /// Metadata describing a write by this crate version, now.
+    pub fn current() -> Self {
+        Self::at(SystemTime::now())
+    }
+
+    /// Metadata describing a write by this crate version at `time`, e.g. from a [`crate::Clock`].
+    pub(crate) fn at(time: SystemTime) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            written_at: time
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        }
+    }
+
+    /// Returns `true` if the device ID was stored by a different version of this crate.
+    pub fn is_other_version(&self) -> bool {
+        self.version != env!("CARGO_PKG_VERSION")
+    }
+
+    pub(crate) fn to_json(&self) -> String {
+        format!(
+            r#"{{"version":"{}","written_at":{}}}"#,
+            self.version, self.written_at
+        )
+    }
+
+    /// Parses the flat JSON object written by [`Metadata::to_json`], returning `None` if it is malformed.
+    pub(crate) fn from_json(json: &str) -> Option<Self> {
+        let body = json.trim().strip_prefix('{')?.strip_suffix('}')?;
+        let mut version = None;
+        let mut written_at = None;
+        for field in body.split(',') {
+            let (key, value) = field.split_once(':')?;
+            let value = value.trim();
+            match key.trim() {
+                r#""version""# => {
+                    let s = value.strip_prefix('"')?.strip_suffix('"')?;
+                    if s.contains(['"', '\\']) {
+                        return None;
+                    }
+                    version = Some(s.to_string());
+                }
+                r#""written_at""# => written_at = Some(value.parse().ok()?),
+                _ => {}
+            }
+        }
+        Some(Self {
+            version: version?,
+            written_at: written_at?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_round_trip() {
+        let metadata = Metadata::current();
+        assert_eq!(Metadata::from_json(&metadata.to_json()), Some(metadata));
+    }
+
+    #[test]
+    fn test_malformed_json() {
+        for json in [
+            "",
+            "{}",
+            "not json",
+            r#"{"version":"1.0.0"}"#,
+            r#"{"version":1,"written_at":5}"#,
+            r#"{"version":"1.0.0","written_at":"yesterday"}"#,
+        ] {
+            assert_eq!(Metadata::from_json(json), None, "{json}");
+        }
+    }
+
+    #[test]
+    fn test_other_version() {
+        let mut metadata = Metadata::current();
+        assert!(!metadata.is_other_version());
+        metadata.version = "0.0.1-old".to_string();
+        assert!(metadata.is_other_version());
+    }
+}
@@ -0,0 +1,75 @@
+use crate::DevDeviceId;
+
+/// Crockford's base32 alphabet: digits and uppercase letters without `I`, `L`, `O` and `U`.
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+/// Bytes of the UUID encoded in a short code: 40 bits, or 8 characters.
+const SHORT_CODE_BYTES: usize = 5;
+
+impl DevDeviceId {
+    /// A short code for reading the device ID aloud, e.g. `AM78-8072`: the first 40 bits of the UUID
+    /// in Crockford's base32, in two groups of four characters.
+    ///
+    /// The code is deterministic but not unique, so use it for display and lookup by a person, never as
+    /// an identifier. It does not affect storage.
+    pub fn short_code(&self) -> String {
+        let bits = self.0.as_bytes()[..SHORT_CODE_BYTES]
+            .iter()
+            .fold(0u64, |bits, &byte| bits << 8 | u64::from(byte));
+        let mut code = String::with_capacity(9);
+        for i in 0..8 {
+            if i == 4 {
+                code.push('-');
+            }
+            let digit = (bits >> (35 - 5 * i)) & 0x1f;
+            code.push(ALPHABET[digit as usize] as char);
+        }
+        code
+    }
+
+    /// Returns `true` if `code` is a non-empty prefix of [`DevDeviceId::short_code`], as typed by a person:
+    /// case and hyphens are ignored, and `I`/`L` and `O` are read as `1` and `0`, as Crockford's base32 does.
+    pub fn matches_short_code(&self, code: &str) -> bool {
+        let expected = self.short_code().replace('-', "");
+        let mut typed = String::with_capacity(code.len());
+        for c in code.chars().filter(|&c| c != '-') {
+            typed.push(match c.to_ascii_uppercase() {
+                'I' | 'L' => '1',
+                'O' => '0',
+                c => c,
+            });
+        }
+        !typed.is_empty() && expected.starts_with(&typed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short_code_known_value() {
+        let id = DevDeviceId::from_u128(0x550e8400_e29b_41d4_a716_446655440000);
+        assert_eq!(id.short_code(), "AM78-8072");
+    }
+
+    #[test]
+    fn test_short_code_deterministic() {
+        let id = DevDeviceId::generate();
+        assert_eq!(id.short_code(), id.short_code());
+        assert_eq!(id.short_code(), id.clone().short_code());
+        assert_eq!(id.short_code().len(), 9);
+    }
+
+    #[test]
+    fn test_matches_short_code() {
+        let id = DevDeviceId::from_u128(0x550e8400_e29b_41d4_a716_446655440000);
+        assert!(id.matches_short_code("AM78-8072"));
+        assert!(id.matches_short_code("am788072"));
+        assert!(id.matches_short_code("am78"));
+        assert!(id.matches_short_code("AM78-8O72"));
+        assert!(!id.matches_short_code("AM79"));
+        assert!(!id.matches_short_code(""));
+        assert!(!id.matches_short_code("-"));
+        assert!(!id.matches_short_code("AM78-8072-0"));
+    }
+}
@@ -0,0 +1,436 @@
+use crate::{DevDeviceId, Error, Result};
+
+pub use crate::file::FileStorage;
+
+#[cfg(all(
+    any(target_family = "unix", target_os = "wasi"),
+    feature = "mac-fallback"
+))]
+pub use crate::unix::mac_address;
+#[cfg(all(any(target_family = "unix", target_os = "wasi"), feature = "watch"))]
+pub use crate::unix::watch;
+#[cfg(any(target_family = "unix", target_os = "wasi"))]
+pub use crate::unix::{
+    UnixStorage, default_location, dotnet_id, enumerate_apps, home_dir, make_writable,
+    scan_locations, sync_dir,
+};
+#[cfg(all(
+    not(any(target_family = "unix", target_family = "windows", target_os = "wasi")),
+    feature = "mac-fallback"
+))]
+pub use crate::unsupported::mac_address;
+#[cfg(all(
+    not(any(target_family = "unix", target_family = "windows", target_os = "wasi")),
+    feature = "watch"
+))]
+pub use crate::unsupported::watch;
+#[cfg(not(any(target_family = "unix", target_family = "windows", target_os = "wasi")))]
+pub use crate::unsupported::{
+    UnsupportedStorage, default_location, dotnet_id, enumerate_apps, home_dir, make_writable,
+    scan_locations, sync_dir,
+};
+#[cfg(all(target_family = "windows", feature = "test-util"))]
+pub use crate::windows::MockWindowsRegistry;
+#[cfg(all(target_family = "windows", feature = "mac-fallback"))]
+pub use crate::windows::mac_address;
+#[cfg(all(target_family = "windows", feature = "watch"))]
+pub use crate::windows::watch;
+#[cfg(target_family = "windows")]
+pub use crate::windows::{
+    LiveRegistry, Registry, RegistryStorage, default_location, dotnet_id, enumerate_apps, home_dir,
+    make_writable, scan_locations, sync_dir,
+};
+
+/// The storage backend used by default on this platform.
+#[cfg(any(target_family = "unix", target_os = "wasi"))]
+pub type DefaultStorage = UnixStorage;
+/// The storage backend used by default on this platform.
+#[cfg(target_family = "windows")]
+pub type DefaultStorage = RegistryStorage;
+/// The storage backend used by default on this platform.
+#[cfg(not(any(target_family = "unix", target_family = "windows", target_os = "wasi")))]
+pub type DefaultStorage = UnsupportedStorage;
+
+/// The kind of backend a device ID is stored in, e.g. for bug reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum StorageKind {
+    /// A file, as with `UnixStorage` on Unix and WASI
+    File,
+    /// The registry, as with `RegistryStorage` on Windows
+    Registry,
+    /// Process memory, as with [`MemoryStorage`]
+    Memory,
+    /// No storage: the target has neither a filesystem nor a registry, e.g. `wasm32-unknown-unknown`
+    Unsupported,
+}
+
+impl StorageKind {
+    /// The kind of [`DefaultStorage`] on this platform.
+    #[cfg(any(target_family = "unix", target_os = "wasi"))]
+    pub(crate) const DEFAULT: Self = StorageKind::File;
+    /// The kind of [`DefaultStorage`] on this platform.
+    #[cfg(target_family = "windows")]
+    pub(crate) const DEFAULT: Self = StorageKind::Registry;
+    /// The kind of [`DefaultStorage`] on this platform.
+    #[cfg(not(any(target_family = "unix", target_family = "windows", target_os = "wasi")))]
+    pub(crate) const DEFAULT: Self = StorageKind::Unsupported;
+}
+
+impl std::fmt::Display for StorageKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            StorageKind::File => "file",
+            StorageKind::Registry => "registry",
+            StorageKind::Memory => "memory",
+            StorageKind::Unsupported => "unsupported",
+        })
+    }
+}
+
+/// A backend that persists the device ID.
+///
+/// All backends share the same semantics: [`Storage::retrieve`] returns `Ok(None)` when no ID is stored,
+/// and [`Storage::store`] fails with [`Error::AlreadySet`] rather than replacing a stored ID,
+/// which only [`Storage::overwrite`] does. [`Storage::clear`] removes the stored ID, if any.
+pub trait Storage {
+    /// Retrieves the stored device ID, returning `None` if none is stored.
+    ///
+    /// Implementations only read: retrieving never creates a file, directory or registry key.
+    fn retrieve(&mut self) -> Result<Option<DevDeviceId>>;
+
+    /// Stores the device ID, returning [`Error::AlreadySet`] if an ID is already stored.
+    fn store(&mut self, id: &DevDeviceId) -> Result<()>;
+
+    /// Stores the device ID, replacing any stored ID.
+    fn overwrite(&mut self, id: &DevDeviceId) -> Result<()>;
+
+    /// Removes the stored device ID, succeeding if none is stored.
+    fn clear(&mut self) -> Result<()>;
+}
+
+/// Stores `id` unless another ID is already stored, and returns the ID in storage afterwards.
+///
+/// If another writer stored an ID first, so that [`Storage::store`] fails with [`Error::AlreadySet`],
+/// its ID is read back and returned instead, so that all racing writers agree on one ID. This is the
+/// last step of [`DevDeviceId::resolve`], for custom backends and callers that generate
+/// the ID themselves.
+///
+/// Once `id` is stored, reading back a different ID, or none, fails with [`Error::VerificationFailed`],
+/// so that a faulty backend is caught rather than handing out an ID that is not the stored one.
+pub fn ensure_stored(storage: &mut impl Storage, id: DevDeviceId) -> Result<DevDeviceId> {
+    match storage.store(&id) {
+        Ok(()) => verify_stored(storage, id),
+        // another writer stored an ID since we looked, so use theirs
+        Err(Error::AlreadySet) => storage.retrieve()?.ok_or(Error::AlreadySet),
+        Err(err) => Err(err),
+    }
+}
+
+/// Reads back the ID just written, failing with [`Error::VerificationFailed`] unless it is `id`.
+pub(crate) fn verify_stored(storage: &mut impl Storage, id: DevDeviceId) -> Result<DevDeviceId> {
+    match storage.retrieve()? {
+        Some(actual) if actual == id => Ok(id),
+        actual => Err(Error::VerificationFailed {
+            expected: id,
+            actual,
+        }),
+    }
+}
+
+/// Keeps the device ID in memory only; nothing is persisted.
+///
+/// Useful for tests, or for an ephemeral ID that should not outlive the process.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    id: Option<DevDeviceId>,
+}
+
+impl MemoryStorage {
+    /// Creates an empty in-memory storage.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an in-memory storage that already holds `id`.
+    pub fn with_id(id: DevDeviceId) -> Self {
+        Self { id: Some(id) }
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn retrieve(&mut self) -> Result<Option<DevDeviceId>> {
+        Ok(self.id.clone())
+    }
+
+    fn store(&mut self, id: &DevDeviceId) -> Result<()> {
+        if self.id.is_some() {
+            return Err(Error::AlreadySet);
+        }
+        self.id = Some(id.clone());
+        Ok(())
+    }
+
+    fn overwrite(&mut self, id: &DevDeviceId) -> Result<()> {
+        self.id = Some(id.clone());
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        self.id = None;
+        Ok(())
+    }
+}
+
+/// Keeps the device ID in two backends, e.g. the default location plus a file elsewhere, so that it
+/// survives one of them being wiped by a cleaner.
+///
+/// [`Storage::retrieve`] returns the ID from the primary backend, or else from the mirror, and heals
+/// whichever backend is missing it, holds a corrupt value or disagrees with the primary by writing the
+/// found ID there, so retrieving may write. A primary that fails to read for another reason, e.g. a
+/// timeout, is left alone, and the mirror's ID is returned.
+/// Writes to the mirror are best-effort: failing to write it does not fail the operation.
+/// [`Storage::clear`] clears both, so that the ID does not come back.
+#[derive(Debug, Clone, Default)]
+pub struct MirrorStorage<P, M> {
+    primary: P,
+    mirror: M,
+}
+
+impl<P: Storage, M: Storage> MirrorStorage<P, M> {
+    /// Mirrors `primary` to `mirror`.
+    pub fn new(primary: P, mirror: M) -> Self {
+        Self { primary, mirror }
+    }
+
+    /// The primary backend.
+    pub fn primary_mut(&mut self) -> &mut P {
+        &mut self.primary
+    }
+
+    /// The mirror backend.
+    pub fn mirror_mut(&mut self) -> &mut M {
+        &mut self.mirror
+    }
+}
+
+impl<P: Storage, M: Storage> Storage for MirrorStorage<P, M> {
+    fn retrieve(&mut self) -> Result<Option<DevDeviceId>> {
+        match self.primary.retrieve() {
+            Ok(Some(id)) => {
+                match self.mirror.retrieve() {
+                    Ok(None) => {
+                        let _ = self.mirror.store(&id);
+                    }
+                    // the primary wins, so that both converge on one ID
+                    Ok(Some(mirrored)) if mirrored != id => {
+                        let _ = self.mirror.overwrite(&id);
+                    }
+                    Err(Error::BadUuidFormat(_)) => {
+                        let _ = self.mirror.overwrite(&id);
+                    }
+                    _ => {}
+                }
+                Ok(Some(id))
+            }
+            Ok(None) => {
+                let id = self.mirror.retrieve()?;
+                if let Some(id) = &id {
+                    // the primary is not best-effort, but a retrieve still succeeds without it
+                    let _ = self.primary.store(id);
+                }
+                Ok(id)
+            }
+            // e.g. a corrupt primary: the mirror may still have the ID
+            Err(err) => match self.mirror.retrieve() {
+                Ok(Some(id)) => {
+                    // only a corrupt primary is repaired: after e.g. a timeout it may still hold a
+                    // newer ID than the mirror
+                    if matches!(err, Error::BadUuidFormat(_)) {
+                        let _ = self.primary.overwrite(&id);
+                    }
+                    Ok(Some(id))
+                }
+                _ => Err(err),
+            },
+        }
+    }
+
+    fn store(&mut self, id: &DevDeviceId) -> Result<()> {
+        if self.retrieve()?.is_some() {
+            return Err(Error::AlreadySet);
+        }
+        self.primary.store(id)?;
+        let _ = self.mirror.store(id);
+        Ok(())
+    }
+
+    fn overwrite(&mut self, id: &DevDeviceId) -> Result<()> {
+        self.primary.overwrite(id)?;
+        let _ = self.mirror.overwrite(id);
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        self.primary.clear()?;
+        self.mirror.clear()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DevDeviceIdBuilder, OverwritePolicy};
+
+    /// Another writer's ID appears between our check and our store.
+    struct RacyStorage {
+        winner: Option<DevDeviceId>,
+    }
+
+    impl Storage for RacyStorage {
+        fn retrieve(&mut self) -> Result<Option<DevDeviceId>> {
+            Ok(self.winner.clone())
+        }
+
+        fn store(&mut self, _id: &DevDeviceId) -> Result<()> {
+            Err(Error::AlreadySet)
+        }
+
+        fn overwrite(&mut self, _id: &DevDeviceId) -> Result<()> {
+            unreachable!()
+        }
+
+        fn clear(&mut self) -> Result<()> {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn test_ensure_stored() {
+        let mut storage = MemoryStorage::new();
+        let id = DevDeviceId::generate();
+        assert_eq!(ensure_stored(&mut storage, id.clone()).unwrap(), id);
+        assert_eq!(storage.retrieve().unwrap(), Some(id.clone()));
+
+        // an already stored ID wins
+        assert_eq!(
+            ensure_stored(&mut storage, DevDeviceId::generate()).unwrap(),
+            id
+        );
+    }
+
+    /// Accepts any ID, but always returns the same, different one.
+    struct MisreadingStorage {
+        stored: bool,
+        returned: DevDeviceId,
+    }
+
+    impl Storage for MisreadingStorage {
+        fn retrieve(&mut self) -> Result<Option<DevDeviceId>> {
+            Ok(self.stored.then(|| self.returned.clone()))
+        }
+
+        fn store(&mut self, _id: &DevDeviceId) -> Result<()> {
+            self.stored = true;
+            Ok(())
+        }
+
+        fn overwrite(&mut self, id: &DevDeviceId) -> Result<()> {
+            self.store(id)
+        }
+
+        fn clear(&mut self) -> Result<()> {
+            self.stored = false;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_ensure_stored_verifies_read_back() {
+        let returned = DevDeviceId::generate();
+        let mut storage = MisreadingStorage {
+            stored: false,
+            returned: returned.clone(),
+        };
+        let result = DevDeviceId::get_or_generate_in(&mut storage);
+        assert!(
+            matches!(&result, Err(Error::VerificationFailed { expected, actual }) if *expected != returned && actual.as_ref() == Some(&returned)),
+            "{result:?}"
+        );
+    }
+
+    /// Accepts any ID, but never keeps it.
+    struct DroppingStorage;
+
+    impl Storage for DroppingStorage {
+        fn retrieve(&mut self) -> Result<Option<DevDeviceId>> {
+            Ok(None)
+        }
+
+        fn store(&mut self, _id: &DevDeviceId) -> Result<()> {
+            Ok(())
+        }
+
+        fn overwrite(&mut self, _id: &DevDeviceId) -> Result<()> {
+            Ok(())
+        }
+
+        fn clear(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_ensure_stored_detects_dropped_write() {
+        let id = DevDeviceId::generate();
+        let result = ensure_stored(&mut DroppingStorage, id.clone());
+        assert!(
+            matches!(&result, Err(Error::VerificationFailed { expected, actual: None }) if *expected == id),
+            "{result:?}"
+        );
+        let message = result.unwrap_err().to_string();
+        assert!(
+            message.ends_with("storage returned no device ID"),
+            "{message}"
+        );
+    }
+
+    #[test]
+    fn test_overwrite_policy_verifies_read_back() {
+        let builder = DevDeviceIdBuilder::new().overwrite_policy(OverwritePolicy::Overwrite);
+        let returned = DevDeviceId::generate();
+        let mut storage = MisreadingStorage {
+            stored: false,
+            returned: returned.clone(),
+        };
+        let result = builder.get_or_generate_in(&mut storage);
+        assert!(
+            matches!(&result, Err(Error::VerificationFailed { expected, actual }) if *expected != returned && actual.as_ref() == Some(&returned)),
+            "{result:?}"
+        );
+
+        let id = DevDeviceId::generate();
+        let result = builder.set_in(&mut DroppingStorage, &id);
+        assert!(
+            matches!(&result, Err(Error::VerificationFailed { expected, actual: None }) if *expected == id),
+            "{result:?}"
+        );
+    }
+
+    #[test]
+    fn test_ensure_stored_lost_race() {
+        let winner = DevDeviceId::generate();
+        let mut storage = RacyStorage {
+            winner: Some(winner.clone()),
+        };
+        assert_eq!(
+            ensure_stored(&mut storage, DevDeviceId::generate()).unwrap(),
+            winner
+        );
+
+        // the winner's ID vanished again before we could read it
+        let mut storage = RacyStorage { winner: None };
+        let result = ensure_stored(&mut storage, DevDeviceId::generate());
+        assert!(matches!(result, Err(Error::AlreadySet)));
+    }
+}
@@ -0,0 +1,211 @@
+#![cfg(feature = "hashing")]
+
+//! Signed tokens carrying the device ID to another machine; see [`DevDeviceId::export_token`].
+
+use crate::{DevDeviceId, DevDeviceIdBuilder, Error, OverwritePolicy, Result, Storage};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use uuid::Uuid;
+
+/// Format of the token payload, signed along with the UUID so that a future format cannot be confused with it.
+const TOKEN_VERSION: u8 = 1;
+/// Bytes of a decoded token: the version, the UUID and the HMAC-SHA256 signature.
+const TOKEN_LEN: usize = 1 + 16 + 32;
+/// The URL-safe base64 alphabet of RFC 4648, used without padding.
+const BASE64URL: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+fn signer(key: &[u8]) -> Result<Hmac<Sha256>> {
+    if key.is_empty() {
+        return Err(Error::InvalidConfiguration(
+            "the token key must not be empty".to_string(),
+        ));
+    }
+    Ok(Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length"))
+}
+
+impl DevDeviceId {
+    /// Exports the stored device ID as a token for [`DevDeviceId::import_token`] on another machine, e.g.
+    /// when a user migrates: the ID signed with HMAC-SHA256 under `key`, in URL-safe base64.
+    ///
+    /// The token is tamper-evident, not secret: anyone can read the ID from it, but only holders of `key`
+    /// can create one that imports. Fails with [`Error::StorageError`] if no ID is stored.
+    pub fn export_token(key: &[u8]) -> Result<String> {
+        DevDeviceIdBuilder::new().export_token(key)
+    }
+
+    /// Verifies a token from [`DevDeviceId::export_token`] under `key` and stores its device ID, replacing
+    /// any stored one. A token that was altered or signed with another key fails with
+    /// [`Error::InvalidToken`], and nothing is stored.
+    pub fn import_token(token: &str, key: &[u8]) -> Result<Self> {
+        DevDeviceIdBuilder::new()
+            .overwrite_policy(OverwritePolicy::Overwrite)
+            .import_token(token, key)
+    }
+
+    /// Signs this device ID as a token, without reading storage. See [`DevDeviceId::export_token`].
+    pub fn to_token(&self, key: &[u8]) -> Result<String> {
+        let mut payload = Vec::with_capacity(TOKEN_LEN);
+        payload.push(TOKEN_VERSION);
+        payload.extend_from_slice(self.0.as_bytes());
+        let mut mac = signer(key)?;
+        mac.update(&payload);
+        payload.extend_from_slice(&mac.finalize().into_bytes());
+        Ok(encode_base64url(&payload))
+    }
+
+    /// Verifies a token under `key` and returns its device ID, without storing it.
+    /// See [`DevDeviceId::import_token`].
+    pub fn from_token(token: &str, key: &[u8]) -> Result<Self> {
+        let mut mac = signer(key)?;
+        let invalid = |reason: &str| Error::InvalidToken(reason.to_string());
+        let payload =
+            decode_base64url(token.trim()).ok_or_else(|| invalid("not URL-safe base64"))?;
+        if payload.len() != TOKEN_LEN {
+            return Err(invalid("unexpected length"));
+        }
+        let (signed, signature) = payload.split_at(1 + 16);
+        mac.update(signed);
+        // compared in constant time
+        mac.verify_slice(signature).map_err(|_| {
+            invalid(
+                "the signature does not match, the token was altered or signed with another key",
+            )
+        })?;
+        if signed[0] != TOKEN_VERSION {
+            return Err(invalid("unsupported version"));
+        }
+        let uuid = Uuid::from_slice(&signed[1..]).map_err(|e| invalid(&e.to_string()))?;
+        Ok(DevDeviceId(uuid))
+    }
+}
+
+impl DevDeviceIdBuilder {
+    /// Exports the stored device ID as a signed token. See [`DevDeviceId::export_token`].
+    pub fn export_token(&self, key: &[u8]) -> Result<String> {
+        self.export_token_in(&mut self.storage()?, key)
+    }
+
+    /// Like [`DevDeviceIdBuilder::export_token`], but using the given storage backend.
+    pub fn export_token_in(&self, storage: &mut impl Storage, key: &[u8]) -> Result<String> {
+        storage
+            .retrieve()?
+            .ok_or_else(|| Error::storage("export_token", "no device ID is stored"))?
+            .to_token(key)
+    }
+
+    /// Verifies a signed token and stores its device ID, applying the [`OverwritePolicy`].
+    /// See [`DevDeviceId::import_token`].
+    pub fn import_token(&self, token: &str, key: &[u8]) -> Result<DevDeviceId> {
+        self.import_token_in(&mut self.storage()?, token, key)
+    }
+
+    /// Like [`DevDeviceIdBuilder::import_token`], but using the given storage backend.
+    pub fn import_token_in(
+        &self,
+        storage: &mut impl Storage,
+        token: &str,
+        key: &[u8],
+    ) -> Result<DevDeviceId> {
+        let id = DevDeviceId::from_token(token, key)?;
+        self.set_in(storage, &id)
+    }
+}
+
+fn encode_base64url(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| {
+            bits | u32::from(byte) << (16 - 8 * i)
+        });
+        // one character per started 6 bits: 2, 3 or 4 per chunk
+        for i in 0..=chunk.len() {
+            encoded.push(BASE64URL[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+    }
+    encoded
+}
+
+fn decode_base64url(text: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(text.len() * 3 / 4);
+    for chunk in text.as_bytes().chunks(4) {
+        if chunk.len() == 1 {
+            return None;
+        }
+        let mut bits = 0u32;
+        for (i, &c) in chunk.iter().enumerate() {
+            let value = BASE64URL.iter().position(|&a| a == c)? as u32;
+            bits |= value << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            decoded.push((bits >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryStorage;
+
+    const KEY: &[u8] = b"migration-key";
+
+    #[test]
+    fn test_base64url_roundtrip() {
+        for len in 0..8 {
+            let bytes: Vec<u8> = (0..len).map(|i| 0xfb ^ (i as u8 * 37)).collect();
+            let encoded = encode_base64url(&bytes);
+            assert!(!encoded.contains(['+', '/', '=']));
+            assert_eq!(decode_base64url(&encoded), Some(bytes));
+        }
+        assert_eq!(encode_base64url(b"\xfb\xff"), "-_8");
+        assert_eq!(decode_base64url("a"), None);
+        assert_eq!(decode_base64url("a+b/"), None);
+    }
+
+    #[test]
+    fn test_token_roundtrip() {
+        let id = DevDeviceId::generate();
+        let mut source = MemoryStorage::with_id(id.clone());
+        let builder = DevDeviceIdBuilder::new();
+        let token = builder.export_token_in(&mut source, KEY).unwrap();
+        assert_eq!(token, id.to_token(KEY).unwrap());
+
+        let mut target = MemoryStorage::with_id(DevDeviceId::generate());
+        let builder = builder.overwrite_policy(OverwritePolicy::Overwrite);
+        assert_eq!(
+            builder.import_token_in(&mut target, &token, KEY).unwrap(),
+            id
+        );
+        assert_eq!(target.retrieve().unwrap(), Some(id));
+
+        let result = DevDeviceIdBuilder::new().export_token_in(&mut MemoryStorage::new(), KEY);
+        assert!(matches!(result, Err(Error::StorageError(_))));
+    }
+
+    #[test]
+    fn test_tampered_token_rejected() {
+        let id = DevDeviceId::generate();
+        let token = id.to_token(KEY).unwrap();
+        let mut target = MemoryStorage::new();
+        let builder = DevDeviceIdBuilder::new();
+
+        // another ID spliced into the token
+        let mut bytes = decode_base64url(&token).unwrap();
+        bytes[1] ^= 0x01;
+        let tampered = encode_base64url(&bytes);
+        let result = builder.import_token_in(&mut target, &tampered, KEY);
+        assert!(matches!(result, Err(Error::InvalidToken(msg)) if msg.contains("signature")));
+
+        let result = builder.import_token_in(&mut target, &token, b"another-key");
+        assert!(matches!(result, Err(Error::InvalidToken(_))));
+        let result = builder.import_token_in(&mut target, &token[..token.len() - 4], KEY);
+        assert!(matches!(result, Err(Error::InvalidToken(_))));
+        let result = builder.import_token_in(&mut target, "not a token", KEY);
+        assert!(matches!(result, Err(Error::InvalidToken(_))));
+        assert_eq!(target.retrieve().unwrap(), None);
+
+        let result = DevDeviceId::from_token(&token, b"");
+        assert!(matches!(result, Err(Error::InvalidConfiguration(_))));
+    }
+}
@@ -1,78 +1,698 @@
-#![cfg(target_family = "unix")]
+#![cfg(any(target_family = "unix", target_os = "wasi"))]
 
-use crate::{DevDeviceId, Result};
+use crate::{
+    BaseDir, DevDeviceId, DevDeviceIdBuilder, Env, FileStorage, Result, Storage, StorageLocation,
+    process_env,
+};
+use std::path::PathBuf;
 
-const DEV_DEVICEID_PATH: &str = "Microsoft/DeveloperTools";
-const FILENAME: &str = "deviceid";
+const DEV_DEVICEID_PATH: &str = crate::UNIX_SUBPATH;
+const FILENAME: &str = crate::STORAGE_KEY;
+/// Where some .NET tooling stores the device ID, relative to `$HOME`.
+const DOTNET_PATH: &str = ".microsoft/developer";
 
+/// Reads an environment variable as a path, treating it as unset unless it is absolute:
+/// an empty or relative value would otherwise silently resolve against the current directory.
+fn absolute_path_var(env: Env, name: &str) -> Option<PathBuf> {
+    env(name)
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+}
+
+/// The Application Support directory under `$HOME`.
 #[cfg(target_os = "macos")]
-fn root_path() -> Result<std::path::PathBuf> {
+fn home_support_dir(env: Env) -> Option<PathBuf> {
     const BASE_STORAGE_PATH: &str = "Library/Application Support";
-    let home = std::env::var_os("HOME");
-    match home {
-        Some(home) => {
-            let mut path = std::path::PathBuf::from(home);
-            path.push(BASE_STORAGE_PATH);
-            Ok(path)
-        }
-        None => Err(super::Error::StorageError(
-            "HOME environment variable not set".to_string(),
-        )),
-    }
+    absolute_path_var(env, "HOME").map(|mut path| {
+        path.push(BASE_STORAGE_PATH);
+        path
+    })
 }
 
+/// The Application Support directory: [`MACOS_SUPPORT_DIR_ENV_VAR`](crate::MACOS_SUPPORT_DIR_ENV_VAR)
+/// if it is set, otherwise the one under `$HOME`.
+#[cfg(target_os = "macos")]
+fn root_path_from(env: Env) -> Result<PathBuf> {
+    absolute_path_var(env, crate::MACOS_SUPPORT_DIR_ENV_VAR)
+        .or_else(|| home_support_dir(env))
+        .ok_or_else(|| {
+            super::Error::StorageError(
+                "HOME environment variable not set, or not an absolute path".to_string(),
+            )
+        })
+}
+
+/// The XDG base directory variable holding the root, and its fallback relative to `$HOME`.
 #[cfg(target_os = "linux")]
-fn root_path() -> Result<std::path::PathBuf> {
-    std::env::var_os("XDG_CACHE_HOME")
-        .map(std::path::PathBuf::from)
+const XDG_ROOT: (&str, &str) = ("XDG_CACHE_HOME", ".cache");
+/// The XDG base directory variable holding the root, and its fallback relative to `$HOME`.
+///
+/// Other Unix systems (e.g. FreeBSD, OpenBSD, illumos) use the state directory, which, unlike the cache,
+/// is not expected to be cleared.
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+const XDG_ROOT: (&str, &str) = ("XDG_STATE_HOME", ".local/state");
+
+#[cfg(not(target_os = "macos"))]
+fn root_path_from(env: Env) -> Result<PathBuf> {
+    xdg_root_from(env, XDG_ROOT)
+}
+
+/// The XDG base directory variable for `base_dir`, and its fallback relative to `$HOME`.
+fn xdg_dir(base_dir: BaseDir) -> (&'static str, &'static str) {
+    match base_dir {
+        BaseDir::Cache => ("XDG_CACHE_HOME", ".cache"),
+        BaseDir::State => ("XDG_STATE_HOME", ".local/state"),
+        BaseDir::Config => ("XDG_CONFIG_HOME", ".config"),
+        BaseDir::Data => ("XDG_DATA_HOME", ".local/share"),
+    }
+}
+
+/// The XDG base directory `var` if it is absolute, otherwise `home_relative` under `$HOME`.
+fn xdg_root_from(env: Env, (var, home_relative): (&str, &str)) -> Result<PathBuf> {
+    absolute_path_var(env, var)
         .or_else(|| {
-            std::env::var_os("HOME").map(|home| {
-                let mut path = std::path::PathBuf::from(home);
-                path.push(".cache");
+            absolute_path_var(env, "HOME").map(|mut path| {
+                path.push(home_relative);
                 path
             })
         })
         .ok_or_else(|| {
-            super::Error::StorageError(
-                "XDG_CACHE_HOME and HOME environment variables not set".to_string(),
-            )
+            super::Error::StorageError(format!(
+                "{var} and HOME environment variables not set, or not absolute paths"
+            ))
         })
 }
 
-fn folder_path() -> Result<std::path::PathBuf> {
-    let mut path = root_path()?;
+/// All roots a device ID may have been stored under, most preferred first.
+#[cfg(target_os = "macos")]
+fn candidate_root_paths(env: Env) -> Result<Vec<PathBuf>> {
+    let root = root_path_from(env)?;
+    let home_root = home_support_dir(env).filter(|home_root| *home_root != root);
+    Ok(std::iter::once(root).chain(home_root).collect())
+}
+
+/// All roots a device ID may have been stored under, most preferred first.
+#[cfg(not(target_os = "macos"))]
+fn candidate_root_paths(env: Env) -> Result<Vec<PathBuf>> {
+    let mut roots = vec![root_path_from(env)?];
+    if let Some(mut home_root) = absolute_path_var(env, "HOME") {
+        home_root.push(XDG_ROOT.1);
+        if !roots.contains(&home_root) {
+            roots.push(home_root);
+        }
+    }
+    Ok(roots)
+}
+
+pub fn scan_locations() -> Result<Vec<(StorageLocation, Result<Option<DevDeviceId>>)>> {
+    scan_locations_from(&process_env)
+}
+
+fn scan_locations_from(env: Env) -> Result<Vec<(StorageLocation, Result<Option<DevDeviceId>>)>> {
+    Ok(candidate_root_paths(env)?
+        .into_iter()
+        .map(|mut path| {
+            path.push(DEV_DEVICEID_PATH);
+            path.push(FILENAME);
+            let id = UnixStorage::at(&path).retrieve();
+            (StorageLocation::File(path), id)
+        })
+        .collect())
+}
+
+pub fn enumerate_apps() -> Result<Vec<(String, Result<DevDeviceId>)>> {
+    enumerate_apps_from(&process_env)
+}
+
+fn enumerate_apps_from(env: Env) -> Result<Vec<(String, Result<DevDeviceId>)>> {
+    let root = folder_path(env, None, None)?;
+    let entries = match std::fs::read_dir(&root) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(crate::Error::storage("read_dir", e)),
+    };
+    let mut apps = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|e| crate::Error::storage("read_dir", e))?;
+        if !entry.path().is_dir() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().into_owned();
+        match UnixStorage::at(entry.path().join(FILENAME)).retrieve() {
+            Ok(Some(id)) => apps.push((name, Ok(id))),
+            Ok(None) => {}
+            Err(err) => apps.push((name, Err(err))),
+        }
+    }
+    apps.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(apps)
+}
+
+pub fn default_location(storage: &UnixStorage) -> Result<StorageLocation> {
+    Ok(storage.location())
+}
+
+/// The user's home directory, `$HOME`, see [`StorageLocation::display`].
+pub fn home_dir() -> Option<PathBuf> {
+    absolute_path_var(&process_env, "HOME")
+}
+
+/// The ID stored by .NET tooling, see [`DevDeviceIdBuilder::dotnet_interop`].
+pub fn dotnet_id() -> Option<DevDeviceId> {
+    dotnet_id_from(&process_env)
+}
+
+/// Reads `$HOME/.microsoft/developer/deviceid`, treating an unreadable or corrupt ID as absent:
+/// the file belongs to other tools.
+fn dotnet_id_from(env: Env) -> Option<DevDeviceId> {
+    let mut path = absolute_path_var(env, "HOME")?;
+    path.push(DOTNET_PATH);
+    path.push(FILENAME);
+    UnixStorage::at(path).retrieve().ok().flatten()
+}
+
+/// The first stable MAC address, by interface name, for [`DevDeviceId::from_mac_fallback`].
+#[cfg(all(feature = "mac-fallback", target_os = "linux"))]
+pub fn mac_address() -> Option<[u8; 6]> {
+    mac_address_in(std::path::Path::new("/sys/class/net"))
+}
+
+/// MAC addresses are only read on Linux, see [`DevDeviceId::from_mac_fallback`].
+#[cfg(all(feature = "mac-fallback", not(target_os = "linux")))]
+pub fn mac_address() -> Option<[u8; 6]> {
+    None
+}
+
+/// Reads `<dir>/<interface>/address` as sysfs lays out network interfaces.
+#[cfg(all(feature = "mac-fallback", target_os = "linux"))]
+fn mac_address_in(dir: &std::path::Path) -> Option<[u8; 6]> {
+    let mut interfaces: Vec<_> = std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    interfaces.sort();
+    interfaces.iter().find_map(|interface| {
+        let address = std::fs::read_to_string(interface.join("address")).ok()?;
+        crate::mac::parse_stable_mac(&address)
+    })
+}
+
+/// Persists the entries of the directory at `path`, e.g. a file just moved into it. WASI cannot
+/// open a directory as a file, so there this does nothing.
+pub fn sync_dir(path: &std::path::Path) -> std::io::Result<()> {
+    #[cfg(target_family = "unix")]
+    std::fs::File::open(path)?.sync_all()?;
+    #[cfg(not(target_family = "unix"))]
+    let _ = path;
+    Ok(())
+}
+
+/// Lets the owner of the file at `path` write it again, e.g. after [`FileStorage::seal`].
+pub fn make_writable(path: &std::path::Path) -> std::io::Result<()> {
+    let mut permissions = std::fs::metadata(path)?.permissions();
+    #[cfg(target_family = "unix")]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        permissions.set_mode(permissions.mode() | 0o200);
+    }
+    // WASI has no modes, only the read-only flag
+    #[cfg(not(target_family = "unix"))]
+    #[allow(clippy::permissions_set_readonly_false)]
+    permissions.set_readonly(false);
+    std::fs::set_permissions(path, permissions)
+}
+
+/// The directory of the ID file is watched natively where supported, otherwise storage is polled;
+/// see [`DeviceIdWatcher`](crate::DeviceIdWatcher).
+#[cfg(feature = "watch")]
+pub fn watch(storage: UnixStorage) -> Result<crate::DeviceIdWatcher> {
+    let path = storage.path().to_path_buf();
+    Ok(native_watch(&path)
+        .unwrap_or_else(|| crate::DeviceIdWatcher::poll(storage, crate::watch::POLL_INTERVAL)))
+}
+
+/// Watches the directory of the file at `path` with inotify or kqueue, if it exists.
+#[cfg(all(feature = "watch", any(target_os = "linux", target_os = "macos")))]
+fn native_watch(path: &std::path::Path) -> Option<crate::DeviceIdWatcher> {
+    use crate::WatchEvent;
+    use notify::{EventKind, RecursiveMode, Watcher};
+
+    let dir = path.parent().filter(|dir| dir.is_dir())?;
+    let name = path.file_name()?.to_owned();
+    let file = path.to_path_buf();
+    let mut existed = file.exists();
+    let (sender, events) = std::sync::mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        // the directory also holds the metadata file and temporary files of atomic writes
+        let ours = event.paths.iter().any(|p| p.file_name() == Some(&name));
+        if !ours || matches!(event.kind, EventKind::Access(_)) {
+            return;
+        }
+        // a rename over the file is reported differently per platform, so compare existence instead
+        let exists = file.exists();
+        let change = match (existed, exists) {
+            (false, true) => WatchEvent::Created,
+            (true, true) => WatchEvent::Modified,
+            (true, false) => WatchEvent::Removed,
+            (false, false) => return,
+        };
+        existed = exists;
+        let _ = sender.send(change);
+    })
+    .ok()?;
+    watcher.watch(dir, RecursiveMode::NonRecursive).ok()?;
+    Some(crate::DeviceIdWatcher::native(events, watcher))
+}
+
+#[cfg(all(feature = "watch", not(any(target_os = "linux", target_os = "macos"))))]
+fn native_watch(_path: &std::path::Path) -> Option<crate::DeviceIdWatcher> {
+    None
+}
+
+/// The root under `base_dir` if one is selected, otherwise the default root.
+fn base_root_from(env: Env, base_dir: Option<BaseDir>) -> Result<PathBuf> {
+    match base_dir {
+        Some(base_dir) => xdg_root_from(env, xdg_dir(base_dir)),
+        None => root_path_from(env),
+    }
+}
+
+fn folder_path(env: Env, base_dir: Option<BaseDir>, app_name: Option<&str>) -> Result<PathBuf> {
+    let mut path = base_root_from(env, base_dir)?;
     path.push(DEV_DEVICEID_PATH);
+    if let Some(app_name) = app_name {
+        path.push(app_name);
+    }
     Ok(path)
 }
 
-fn path() -> Result<std::path::PathBuf> {
-    let mut path = folder_path()?;
+fn path(env: Env, base_dir: Option<BaseDir>, app_name: Option<&str>) -> Result<PathBuf> {
+    let mut path = folder_path(env, base_dir, app_name)?;
     path.push(FILENAME);
     Ok(path)
 }
 
-pub fn retrieve() -> Result<Option<DevDeviceId>> {
-    let path = path()?;
-    if path.exists() {
-        // TODO: don't read too much!
-        let data = std::fs::read(path).map_err(|e| super::Error::StorageError(e.to_string()))?;
-        let id = uuid::Uuid::try_parse_ascii(data.as_slice())
-            .map_err(|e| super::Error::BadUuidFormat(e.to_string()))?;
-        Ok(Some(DevDeviceId(id)))
-    } else {
-        Ok(None)
-    }
-}
-
-pub fn store(id: &DevDeviceId) -> Result<()> {
-    std::fs::create_dir_all(folder_path()?)
-        .map_err(|e| super::Error::StorageError(e.to_string()))?;
-    if !path()?.exists() {
-        let id_str = format!("{id}");
-        std::fs::write(path()?, id_str.as_bytes())
-            .map_err(|e| super::Error::StorageError(e.to_string()))?;
-        Ok(())
-    } else {
-        Err(super::Error::AlreadySet)
+/// Stores the device ID in a file, by default under the user's cache directory
+/// (`Library/Application Support` on macOS).
+///
+/// This is a [`FileStorage`] whose [`UnixStorage::new`] resolves the default location.
+pub type UnixStorage = FileStorage;
+
+impl FileStorage {
+    /// Creates a storage for the default file location, resolved from the environment.
+    pub fn new() -> Result<Self> {
+        Ok(Self::at(path(&process_env, None, None)?))
+    }
+
+    pub(crate) fn configured(builder: &DevDeviceIdBuilder) -> Result<Self> {
+        Self::configured_from(builder, &process_env)
+    }
+
+    fn configured_from(builder: &DevDeviceIdBuilder, env: Env) -> Result<Self> {
+        Ok(Self::configured_at(
+            builder,
+            path(env, builder.base_dir, builder.app_name.as_deref())?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Fixture, fake_env};
+
+    #[cfg(all(feature = "mac-fallback", target_os = "linux"))]
+    #[test]
+    fn test_mac_address_skips_unstable_interfaces() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        for (interface, address) in [
+            ("lo", "00:00:00:00:00:00"),
+            ("eth0", "00:1a:2b:3c:4d:5e"),
+            ("eth1", "00:1a:2b:3c:4d:5f"),
+            ("docker0", "02:42:ac:11:00:02"),
+        ] {
+            std::fs::create_dir(dir.join(interface)).unwrap();
+            std::fs::write(dir.join(interface).join("address"), format!("{address}\n")).unwrap();
+        }
+        assert_eq!(
+            mac_address_in(dir),
+            Some([0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e])
+        );
+    }
+
+    #[test]
+    fn test_dotnet_interop_adopts_dotnet_id() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        let env = fake_env(&[("HOME", dir.to_str().unwrap()), ("XDG_CACHE_HOME", "")]);
+        assert_eq!(dotnet_id_from(&env), None);
+
+        let dotnet_file = dir.join(DOTNET_PATH).join(FILENAME);
+        let dotnet = DevDeviceId::generate();
+        UnixStorage::at(&dotnet_file).store(&dotnet).unwrap();
+        assert_eq!(dotnet_id_from(&env), Some(dotnet.clone()));
+
+        let builder = DevDeviceIdBuilder::new().dotnet_interop(crate::DotnetInterop::Copy);
+        let mut storage = UnixStorage::configured_from(&builder, &env).unwrap();
+        let adopted = builder.retrieve_adopting(&mut storage, || dotnet_id_from(&env), true);
+        assert_eq!(adopted.unwrap(), Some(dotnet.clone()));
+        assert_eq!(storage.retrieve().unwrap(), Some(dotnet.clone()));
+        // the .NET tooling file is left as is
+        assert_eq!(
+            UnixStorage::at(&dotnet_file).retrieve().unwrap(),
+            Some(dotnet)
+        );
+
+        std::fs::write(&dotnet_file, "not-a-uuid").unwrap();
+        assert_eq!(dotnet_id_from(&env), None);
+    }
+
+    #[test]
+    fn test_root_path_rejects_empty_home() {
+        let result = root_path_from(&fake_env(&[("HOME", "")]));
+        assert!(matches!(result, Err(crate::Error::StorageError(_))));
+    }
+
+    #[test]
+    fn test_root_path_rejects_relative_home() {
+        let result = root_path_from(&fake_env(&[("HOME", "relative/home")]));
+        assert!(matches!(result, Err(crate::Error::StorageError(_))));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_root_path_ignores_invalid_xdg_cache_home() {
+        let expected = PathBuf::from("/home/user/.cache");
+        let vars = &[("XDG_CACHE_HOME", ""), ("HOME", "/home/user")];
+        assert_eq!(root_path_from(&fake_env(vars)).unwrap(), expected);
+        let vars = &[("XDG_CACHE_HOME", "relative/cache"), ("HOME", "/home/user")];
+        assert_eq!(root_path_from(&fake_env(vars)).unwrap(), expected);
+
+        let result = root_path_from(&fake_env(&[("XDG_CACHE_HOME", "")]));
+        assert!(matches!(result, Err(crate::Error::StorageError(_))));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_root_path_prefers_xdg_cache_home() {
+        let vars = &[("XDG_CACHE_HOME", "/xdg/cache"), ("HOME", "/home/user")];
+        assert_eq!(
+            root_path_from(&fake_env(vars)).unwrap(),
+            PathBuf::from("/xdg/cache")
+        );
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn test_root_path_uses_home() {
+        let path = root_path_from(&fake_env(&[("HOME", "/Users/user")])).unwrap();
+        assert_eq!(
+            path,
+            PathBuf::from("/Users/user/Library/Application Support")
+        );
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn test_macos_support_dir_override() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        let support_dir = dir.to_str().unwrap();
+        let env = fake_env(&[
+            (crate::MACOS_SUPPORT_DIR_ENV_VAR, support_dir),
+            ("HOME", "/Users/user"),
+        ]);
+        let mut storage = UnixStorage::configured_from(&DevDeviceIdBuilder::new(), &env).unwrap();
+        let id = DevDeviceId::get_or_generate_in(&mut storage).unwrap();
+        let file = dir.join(DEV_DEVICEID_PATH).join(FILENAME);
+        assert_eq!(storage.path(), file);
+        assert_eq!(UnixStorage::at(&file).retrieve().unwrap(), Some(id));
+
+        // a relative override is ignored
+        let env = fake_env(&[
+            (crate::MACOS_SUPPORT_DIR_ENV_VAR, "relative"),
+            ("HOME", "/Users/user"),
+        ]);
+        assert_eq!(
+            root_path_from(&env).unwrap(),
+            PathBuf::from("/Users/user/Library/Application Support")
+        );
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux")))]
+    #[test]
+    fn test_root_path_prefers_xdg_state_home() {
+        let vars = &[("XDG_STATE_HOME", "/xdg/state"), ("HOME", "/home/user")];
+        assert_eq!(
+            root_path_from(&fake_env(vars)).unwrap(),
+            PathBuf::from("/xdg/state")
+        );
+        let vars = &[("XDG_STATE_HOME", "relative/state"), ("HOME", "/home/user")];
+        assert_eq!(
+            root_path_from(&fake_env(vars)).unwrap(),
+            PathBuf::from("/home/user/.local/state")
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_scan_locations_finds_conflicting_ids() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        let xdg = dir.join("xdg");
+        let home = dir.join("home");
+        let xdg_file = xdg.join(DEV_DEVICEID_PATH).join(FILENAME);
+        let home_file = home.join(".cache").join(DEV_DEVICEID_PATH).join(FILENAME);
+        let id = DevDeviceId::generate();
+        let id2 = DevDeviceId::generate();
+        UnixStorage::at(&xdg_file).store(&id).unwrap();
+        UnixStorage::at(&home_file).store(&id2).unwrap();
+
+        let env = fake_env(&[
+            ("XDG_CACHE_HOME", xdg.to_str().unwrap()),
+            ("HOME", home.to_str().unwrap()),
+        ]);
+        let locations = scan_locations_from(&env).unwrap();
+        let paths: Vec<_> = locations.iter().map(|(location, _)| location).collect();
+        assert_eq!(
+            paths,
+            [
+                &StorageLocation::File(xdg_file.clone()),
+                &StorageLocation::File(home_file.clone()),
+            ]
+        );
+        assert_eq!(locations[0].1.as_ref().unwrap(), &Some(id.clone()));
+        assert_eq!(locations[1].1.as_ref().unwrap(), &Some(id2));
+        // scanning does not modify anything
+        assert_eq!(
+            UnixStorage::at(&xdg_file).retrieve().unwrap(),
+            Some(id.clone())
+        );
+
+        std::fs::remove_file(&home_file).unwrap();
+        let locations = scan_locations_from(&env).unwrap();
+        assert_eq!(locations[1].1.as_ref().unwrap(), &None);
+
+        // a corrupt location is reported without hiding the others
+        std::fs::write(&home_file, "not-a-uuid").unwrap();
+        let locations = scan_locations_from(&env).unwrap();
+        assert_eq!(locations[0].1.as_ref().unwrap(), &Some(id));
+        assert!(matches!(
+            locations[1].1,
+            Err(crate::Error::BadUuidFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_enumerate_apps() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        let env = fake_env(&[("HOME", dir.to_str().unwrap())]);
+        assert!(enumerate_apps_from(&env).unwrap().is_empty());
+
+        let builder = DevDeviceIdBuilder::new();
+        let shared = DevDeviceId::generate();
+        UnixStorage::configured_from(&builder, &env)
+            .unwrap()
+            .store(&shared)
+            .unwrap();
+        let mut ids = Vec::new();
+        for app in ["beta", "alpha"] {
+            let builder = DevDeviceIdBuilder::new().app_name(app);
+            let mut storage = UnixStorage::configured_from(&builder, &env).unwrap();
+            ids.push(DevDeviceId::get_or_generate_in(&mut storage).unwrap());
+        }
+        let root = folder_path(&env, None, None).unwrap();
+        std::fs::create_dir_all(root.join("empty")).unwrap();
+        std::fs::create_dir_all(root.join("corrupt")).unwrap();
+        std::fs::write(root.join("corrupt").join(FILENAME), "not-a-uuid").unwrap();
+
+        let apps = enumerate_apps_from(&env).unwrap();
+        let names: Vec<_> = apps.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["alpha", "beta", "corrupt"]);
+        assert_eq!(apps[0].1.as_ref().unwrap(), &ids[1]);
+        assert_eq!(apps[1].1.as_ref().unwrap(), &ids[0]);
+        assert!(matches!(apps[2].1, Err(crate::Error::BadUuidFormat(_))));
+    }
+
+    #[cfg(all(feature = "watch", any(target_os = "linux", target_os = "macos")))]
+    #[test]
+    fn test_native_watch() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        let path = dir.join("deviceid");
+        let watcher = native_watch(&path).unwrap();
+        crate::watch::assert_reports_changes(&watcher, &mut FileStorage::at(&path));
+        drop(watcher);
+    }
+
+    #[test]
+    fn test_scan_locations_empty() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        let env = fake_env(&[("HOME", dir.to_str().unwrap())]);
+        let locations = scan_locations_from(&env).unwrap();
+        assert_eq!(locations.len(), 1);
+        assert_eq!(locations[0].1.as_ref().unwrap(), &None);
+    }
+
+    #[test]
+    fn test_app_name_isolation() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        let env = fake_env(&[("HOME", dir.to_str().unwrap()), ("XDG_CACHE_HOME", "")]);
+        let mut default = UnixStorage::configured_from(&DevDeviceIdBuilder::new(), &env).unwrap();
+        let builder = DevDeviceIdBuilder::new().app_name("app-a");
+        let mut app_a = UnixStorage::configured_from(&builder, &env).unwrap();
+        let builder = DevDeviceIdBuilder::new().app_name("app-b");
+        let mut app_b = UnixStorage::configured_from(&builder, &env).unwrap();
+
+        let root = root_path_from(&env).unwrap().join(DEV_DEVICEID_PATH);
+        assert_eq!(default.path(), root.join(FILENAME));
+        assert_eq!(app_a.path(), root.join("app-a").join(FILENAME));
+
+        let id = DevDeviceId::get_or_generate_in(&mut default).unwrap();
+        let id_a = DevDeviceId::get_or_generate_in(&mut app_a).unwrap();
+        let id_b = DevDeviceId::get_or_generate_in(&mut app_b).unwrap();
+        assert_ne!(id, id_a);
+        assert_ne!(id_a, id_b);
+        assert_eq!(default.retrieve().unwrap(), Some(id));
+        assert_eq!(app_a.retrieve().unwrap(), Some(id_a));
+    }
+
+    #[test]
+    fn test_default_path_unchanged() {
+        let env = fake_env(&[
+            ("HOME", "/home/user"),
+            ("XDG_CACHE_HOME", "/home/user/.cache"),
+        ]);
+        let storage = UnixStorage::configured_from(&DevDeviceIdBuilder::new(), &env).unwrap();
+        #[cfg(target_os = "linux")]
+        let expected = "/home/user/.cache/Microsoft/DeveloperTools/deviceid";
+        #[cfg(target_os = "macos")]
+        let expected = "/home/user/Library/Application Support/Microsoft/DeveloperTools/deviceid";
+        #[cfg(not(any(target_os = "macos", target_os = "linux")))]
+        let expected = "/home/user/.local/state/Microsoft/DeveloperTools/deviceid";
+        assert_eq!(storage.path(), std::path::Path::new(expected));
+    }
+
+    #[test]
+    fn test_base_dir_selection() {
+        let vars = &[
+            ("HOME", "/home/user"),
+            ("XDG_CACHE_HOME", "/xdg/cache"),
+            ("XDG_STATE_HOME", "/xdg/state"),
+            ("XDG_CONFIG_HOME", "/xdg/config"),
+            ("XDG_DATA_HOME", "/xdg/data"),
+        ];
+        let cases = [
+            (BaseDir::Cache, "/xdg/cache", "/home/user/.cache"),
+            (BaseDir::State, "/xdg/state", "/home/user/.local/state"),
+            (BaseDir::Config, "/xdg/config", "/home/user/.config"),
+            (BaseDir::Data, "/xdg/data", "/home/user/.local/share"),
+        ];
+        for (base_dir, from_var, from_home) in cases {
+            let builder = DevDeviceIdBuilder::new().base_dir(base_dir);
+            let storage = UnixStorage::configured_from(&builder, &fake_env(vars)).unwrap();
+            let expected = PathBuf::from(from_var)
+                .join(DEV_DEVICEID_PATH)
+                .join(FILENAME);
+            assert_eq!(storage.path(), expected, "{base_dir:?}");
+
+            // a relative or missing variable falls back to `$HOME`
+            let (var, _) = xdg_dir(base_dir);
+            let env = fake_env(&[("HOME", "/home/user"), (var, "relative")]);
+            let storage = UnixStorage::configured_from(&builder, &env).unwrap();
+            let expected = PathBuf::from(from_home)
+                .join(DEV_DEVICEID_PATH)
+                .join(FILENAME);
+            assert_eq!(storage.path(), expected, "{base_dir:?}");
+        }
+
+        let result = UnixStorage::configured_from(
+            &DevDeviceIdBuilder::new().base_dir(BaseDir::Config),
+            &fake_env(&[]),
+        );
+        assert!(matches!(result, Err(crate::Error::StorageError(_))));
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn test_unwritable_dir_is_permission_denied() {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+        let fixture = Fixture::new();
+
+        let dir = fixture.dir();
+        // permissions do not apply to root, e.g. in containers; the directory is owned by our user
+        if std::fs::metadata(dir).unwrap().uid() == 0 {
+            eprintln!("skipped: running as root");
+            return;
+        }
+        let folder = dir.join(DEV_DEVICEID_PATH);
+        std::fs::create_dir_all(&folder).unwrap();
+        std::fs::set_permissions(&folder, std::fs::Permissions::from_mode(0o555)).unwrap();
+        let mut storage = UnixStorage::at(folder.join(FILENAME));
+        let result = DevDeviceId::get_or_generate_in(&mut storage);
+        std::fs::set_permissions(&folder, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(
+            matches!(&result, Err(crate::Error::PermissionDenied { path }) if *path == folder),
+            "{result:?}"
+        );
+        assert!(std::fs::read_dir(&folder).unwrap().next().is_none());
+    }
+
+    #[test]
+    fn test_get_creates_nothing() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        let env = fake_env(&[("HOME", dir.to_str().unwrap()), ("XDG_CACHE_HOME", "")]);
+        let mut storage = UnixStorage::configured_from(&DevDeviceIdBuilder::new(), &env).unwrap();
+        assert_eq!(storage.retrieve().unwrap(), None);
+        assert_eq!(std::fs::read_dir(dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_strict_readonly() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        let env = fake_env(&[("HOME", dir.to_str().unwrap()), ("XDG_CACHE_HOME", "")]);
+        let builder = DevDeviceIdBuilder::new().strict_readonly(true);
+        let mut storage = UnixStorage::configured_from(&builder, &env).unwrap();
+        assert_eq!(DevDeviceId::get_repairing_in(&mut storage).unwrap(), None);
+        let result = builder.get_or_generate_in(&mut storage);
+        assert!(matches!(result, Err(crate::Error::ReadOnly)));
+        assert_eq!(std::fs::read_dir(dir).unwrap().count(), 0);
+
+        // a corrupt value is reported as absent, but left in place
+        let mut writable = UnixStorage::at(storage.path());
+        writable.store(&DevDeviceId::generate()).unwrap();
+        std::fs::write(storage.path(), "not-a-uuid").unwrap();
+        assert_eq!(DevDeviceId::get_repairing_in(&mut storage).unwrap(), None);
+        assert!(storage.path().exists());
     }
 }
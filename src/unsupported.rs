@@ -0,0 +1,126 @@
+#![cfg(not(any(target_family = "unix", target_family = "windows", target_os = "wasi")))]
+
+//! Fallback for targets without persistent storage, e.g. `wasm32-unknown-unknown`: the crate compiles,
+//! and every storage operation fails with [`Error::Unsupported`].
+
+use crate::{DevDeviceId, DevDeviceIdBuilder, Error, Metadata, Result, Storage, StorageLocation};
+
+fn unsupported() -> Error {
+    Error::Unsupported {
+        platform: format!("{}-{}", std::env::consts::ARCH, std::env::consts::OS),
+    }
+}
+
+pub fn scan_locations() -> Result<Vec<(StorageLocation, Result<Option<DevDeviceId>>)>> {
+    Err(unsupported())
+}
+
+pub fn enumerate_apps() -> Result<Vec<(String, Result<DevDeviceId>)>> {
+    Err(unsupported())
+}
+
+#[cfg(feature = "mac-fallback")]
+pub fn mac_address() -> Option<[u8; 6]> {
+    None
+}
+
+#[cfg(feature = "watch")]
+pub fn watch(_storage: UnsupportedStorage) -> Result<crate::DeviceIdWatcher> {
+    Err(unsupported())
+}
+
+/// Fails: there are no files to make writable.
+pub fn make_writable(_path: &std::path::Path) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+/// Does nothing: there are no directories to persist.
+pub fn sync_dir(_path: &std::path::Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+pub fn default_location(_storage: &UnsupportedStorage) -> Result<StorageLocation> {
+    Err(unsupported())
+}
+
+/// There is no home directory without a filesystem, see [`StorageLocation::display`].
+pub fn home_dir() -> Option<std::path::PathBuf> {
+    None
+}
+
+/// There is no .NET tooling ID without a filesystem, see [`DevDeviceIdBuilder::dotnet_interop`].
+pub fn dotnet_id() -> Option<DevDeviceId> {
+    None
+}
+
+/// The default storage on targets without a filesystem or registry: every operation fails with
+/// [`Error::Unsupported`]. Use [`MemoryStorage`](crate::MemoryStorage) or a custom [`Storage`] instead.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UnsupportedStorage;
+
+impl UnsupportedStorage {
+    pub(crate) fn configured(_builder: &DevDeviceIdBuilder) -> Result<Self> {
+        Err(unsupported())
+    }
+
+    /// Fails with [`Error::Unsupported`], as there is nowhere to store the device ID.
+    pub fn preflight(&self) -> Result<()> {
+        Err(unsupported())
+    }
+
+    /// Fails with [`Error::Unsupported`], as there is nowhere to read [`Metadata`] from.
+    pub fn metadata(&self) -> Result<Option<Metadata>> {
+        Err(unsupported())
+    }
+
+    /// Fails with [`Error::Unsupported`], as there is no stored ID to seal.
+    pub fn seal(&self) -> Result<()> {
+        Err(unsupported())
+    }
+
+    /// Fails with [`Error::Unsupported`], as there is no stored ID to seal.
+    pub fn unseal(&self) -> Result<()> {
+        Err(unsupported())
+    }
+
+    /// Fails with [`Error::Unsupported`], as there is no stored ID to seal.
+    pub fn is_sealed(&self) -> Result<bool> {
+        Err(unsupported())
+    }
+}
+
+impl Storage for UnsupportedStorage {
+    fn retrieve(&mut self) -> Result<Option<DevDeviceId>> {
+        Err(unsupported())
+    }
+
+    fn store(&mut self, _id: &DevDeviceId) -> Result<()> {
+        Err(unsupported())
+    }
+
+    fn overwrite(&mut self, _id: &DevDeviceId) -> Result<()> {
+        Err(unsupported())
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        Err(unsupported())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_storage_unsupported() {
+        assert!(matches!(DevDeviceId::get(), Err(Error::Unsupported { .. })));
+        assert!(matches!(
+            DevDeviceId::get_or_generate(),
+            Err(Error::Unsupported { .. })
+        ));
+        assert!(matches!(
+            UnsupportedStorage.store(&DevDeviceId::generate()),
+            Err(Error::Unsupported { .. })
+        ));
+    }
+}
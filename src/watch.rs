@@ -0,0 +1,174 @@
+#![cfg(feature = "watch")]
+
+//! Notification of changes to the stored device ID by any process; see [`DeviceIdWatcher`].
+
+#[cfg(any(target_family = "unix", target_family = "windows", target_os = "wasi"))]
+use crate::Storage;
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// How often storage is read where it cannot be watched natively.
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A change to the stored device ID, reported by [`DeviceIdWatcher`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum WatchEvent {
+    /// A device ID was stored where there was none
+    Created,
+    /// The stored device ID was rewritten
+    Modified,
+    /// The stored device ID was removed, e.g. by a reset
+    Removed,
+}
+
+/// Reports changes to the stored device ID made by any process, e.g. another tool resetting it,
+/// so that a daemon can react immediately.
+///
+/// On Linux and macOS the directory of the ID file is watched with inotify or kqueue. Elsewhere,
+/// including Windows, or if the directory does not exist yet, storage is read every second on a
+/// background thread instead. Watching stops when the watcher is dropped.
+///
+/// Iterating blocks until the next event:
+///
+/// ```no_run
+/// # fn main() -> deviceid::Result<()> {
+/// for event in deviceid::DevDeviceId::watch()? {
+///     println!("{event:?}, the device ID is now {:?}", deviceid::DevDeviceId::get()?);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct DeviceIdWatcher {
+    events: mpsc::Receiver<WatchEvent>,
+    source: Source,
+}
+
+// never constructed where the default storage is unsupported
+#[cfg_attr(
+    not(any(target_family = "unix", target_family = "windows", target_os = "wasi")),
+    allow(dead_code)
+)]
+enum Source {
+    /// The platform's watcher, which stops when dropped
+    Native {
+        _watcher: Box<dyn std::any::Any + Send>,
+    },
+    /// Stops the polling thread when dropped
+    Polling { _stop: mpsc::Sender<()> },
+}
+
+impl DeviceIdWatcher {
+    /// Waits up to `timeout` for the next change.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<WatchEvent> {
+        self.events.recv_timeout(timeout).ok()
+    }
+
+    /// Returns the next change that already happened, if any, without waiting.
+    pub fn try_recv(&self) -> Option<WatchEvent> {
+        self.events.try_recv().ok()
+    }
+
+    /// Reports the `events` sent by a platform watcher, which is kept until the watcher is dropped.
+    #[cfg_attr(not(any(target_os = "linux", target_os = "macos")), allow(dead_code))]
+    pub(crate) fn native(
+        events: mpsc::Receiver<WatchEvent>,
+        watcher: impl std::any::Any + Send,
+    ) -> Self {
+        Self {
+            events,
+            source: Source::Native {
+                _watcher: Box::new(watcher),
+            },
+        }
+    }
+
+    /// Reads `storage` every `interval` on a background thread, reporting when the ID changed.
+    /// Failed reads are skipped.
+    #[cfg(any(target_family = "unix", target_family = "windows", target_os = "wasi"))]
+    pub(crate) fn poll(mut storage: impl Storage + Send + 'static, interval: Duration) -> Self {
+        let (sender, events) = mpsc::channel();
+        let (stop, stopped) = mpsc::channel::<()>();
+        let mut last = storage.retrieve().ok().flatten();
+        std::thread::spawn(move || {
+            while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                let Ok(current) = storage.retrieve() else {
+                    continue;
+                };
+                let change = match (&last, &current) {
+                    (None, Some(_)) => WatchEvent::Created,
+                    (Some(previous), Some(id)) if previous != id => WatchEvent::Modified,
+                    (Some(_), None) => WatchEvent::Removed,
+                    _ => continue,
+                };
+                last = current;
+                if sender.send(change).is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            events,
+            source: Source::Polling { _stop: stop },
+        }
+    }
+}
+
+impl Iterator for DeviceIdWatcher {
+    type Item = WatchEvent;
+
+    fn next(&mut self) -> Option<WatchEvent> {
+        self.events.recv().ok()
+    }
+}
+
+impl std::fmt::Debug for DeviceIdWatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let source = match self.source {
+            Source::Native { .. } => "native",
+            Source::Polling { .. } => "polling",
+        };
+        f.debug_struct("DeviceIdWatcher")
+            .field("source", &source)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Stores, rewrites and removes the ID, expecting `watcher` to report each change.
+#[cfg(all(
+    test,
+    any(target_family = "unix", target_family = "windows", target_os = "wasi")
+))]
+pub(crate) fn assert_reports_changes(watcher: &DeviceIdWatcher, storage: &mut crate::FileStorage) {
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    storage.store(&crate::DevDeviceId::generate()).unwrap();
+    assert_eq!(watcher.recv_timeout(TIMEOUT), Some(WatchEvent::Created));
+    while watcher.recv_timeout(Duration::from_millis(100)).is_some() {}
+
+    storage.overwrite(&crate::DevDeviceId::generate()).unwrap();
+    assert_eq!(watcher.recv_timeout(TIMEOUT), Some(WatchEvent::Modified));
+    while watcher.recv_timeout(Duration::from_millis(100)).is_some() {}
+
+    storage.clear().unwrap();
+    assert_eq!(watcher.recv_timeout(TIMEOUT), Some(WatchEvent::Removed));
+}
+
+#[cfg(all(
+    test,
+    any(target_family = "unix", target_family = "windows", target_os = "wasi")
+))]
+mod tests {
+    use super::*;
+    use crate::{FileStorage, Fixture};
+
+    #[test]
+    fn test_poll_watch() {
+        let fixture = Fixture::new();
+        let dir = fixture.dir();
+        let path = dir.join("deviceid");
+        let watcher = DeviceIdWatcher::poll(FileStorage::at(&path), Duration::from_millis(10));
+        assert_reports_changes(&watcher, &mut FileStorage::at(&path));
+        drop(watcher);
+    }
+}
@@ -1,17 +1,62 @@
 #![cfg(target_family = "windows")]
 
-use crate::{DevDeviceId, Error, Result};
-use windows::Win32::Foundation::ERROR_FILE_NOT_FOUND;
-use windows::Win32::System::Registry::KEY_WOW64_64KEY;
-use windows_registry::{CURRENT_USER, Key, OpenOptions};
+use crate::{
+    Clock, DevDeviceId, DevDeviceIdBuilder, Error, Metadata, Result, Scope, Storage,
+    StorageLocation, StoredFormat, SystemClock,
+};
+use windows::Win32::Foundation::{
+    CloseHandle, ERROR_FILE_NOT_FOUND, ERROR_MORE_DATA, HANDLE, HLOCAL, LocalFree, WAIT_ABANDONED,
+    WAIT_OBJECT_0, WAIT_TIMEOUT,
+};
+use windows::Win32::Security::Authorization::ConvertSidToStringSidW;
+use windows::Win32::Security::{GetTokenInformation, TOKEN_QUERY, TOKEN_USER, TokenUser};
+use windows::Win32::System::Registry::{HKEY, KEY_WOW64_32KEY, KEY_WOW64_64KEY, RegFlushKey};
+use windows::Win32::System::Threading::{
+    CreateMutexW, GetCurrentProcess, OpenProcessToken, ReleaseMutex, WaitForSingleObject,
+};
+use windows::core::{HSTRING, PCWSTR, PWSTR};
+use windows_registry::{CURRENT_USER, Key, LOCAL_MACHINE, OpenOptions, Type};
 use windows_result::HRESULT;
 
-const REGISTRY_PATH: &str = r"SOFTWARE\Microsoft\DeveloperTools";
-const REGISTRY_KEY: &str = "deviceid";
+use std::sync::Arc;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
 
-fn reg_options(create: bool) -> OpenOptions<'static> {
-    let mut options = CURRENT_USER.options();
-    options.read().access(KEY_WOW64_64KEY.0);
+const REGISTRY_PATH: &str = crate::WINDOWS_REGISTRY_PATH;
+const REGISTRY_KEY: &str = crate::STORAGE_KEY;
+const REGISTRY_METADATA_KEY: &str = "deviceid_meta";
+const REGISTRY_PREFLIGHT_KEY: &str = "deviceid_preflight";
+const REGISTRY_MACHINE_GUID_KEY: &str = "deviceid_machine_guid";
+const REGISTRY_SEALED_KEY: &str = "deviceid_sealed";
+/// Where Windows keeps the `MachineGuid` generated at installation, under `HKEY_LOCAL_MACHINE`.
+const MACHINE_GUID_PATH: &str = r"SOFTWARE\Microsoft\Cryptography";
+const MACHINE_GUID_NAME: &str = "MachineGuid";
+/// Most bytes accepted as a stored ID after trimming: the longest textual form (a URN or a checked ID,
+/// 45 bytes) with some slack, so a tampered value is rejected before it is parsed.
+const MAX_ID_LEN: usize = 48;
+/// Most UTF-16 units read from any registry value, including a terminating nul. The values read are all
+/// short (IDs, GUIDs, metadata), so they are read into a fixed buffer rather than allocated at any size.
+const MAX_VALUE_LEN: usize = 256;
+
+/// Serializes storing a new device ID across all processes of a user, in every session; the user's SID
+/// follows. Per user, as the value it guards is, and because a mutex created by another user, e.g. a
+/// service, would deny access to everyone else.
+const STORE_MUTEX_PREFIX: &str = r"Global\MicrosoftDeveloperToolsDeviceId-";
+/// How long to wait for another process to finish storing its device ID.
+const STORE_MUTEX_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn reg_options(scope: Scope, view_32bit: bool, create: bool) -> OpenOptions<'static> {
+    let hive = match scope {
+        Scope::User => CURRENT_USER,
+        Scope::Machine => LOCAL_MACHINE,
+    };
+    let view = if view_32bit {
+        KEY_WOW64_32KEY
+    } else {
+        KEY_WOW64_64KEY
+    };
+    let mut options = hive.options();
+    options.read().access(view.0);
     if create {
         options.write();
         options.create();
@@ -19,45 +64,1328 @@ fn reg_options(create: bool) -> OpenOptions<'static> {
     options
 }
 
-/// Maps [`ERROR_FILE_NOT_FOUND`] to Ok(None), and all other errors to [`Error::StorageError`].
-fn error_not_found_to_none<T>(err: windows_result::Error) -> Result<Option<T>> {
-    match err.code() {
+/// The name of the hive of `scope`, for reports.
+fn hive_name(scope: Scope) -> &'static str {
+    match scope {
+        Scope::User => "HKEY_CURRENT_USER",
+        Scope::Machine => "HKEY_LOCAL_MACHINE",
+    }
+}
+
+/// Maps [`ERROR_FILE_NOT_FOUND`] to Ok(None), and all other errors to [`Error::StorageError`]
+/// labelled with `operation`.
+fn error_not_found_to_none<T>(
+    operation: &'static str,
+) -> impl FnOnce(windows_result::Error) -> Result<Option<T>> {
+    move |err| match err.code() {
         hr if hr == HRESULT::from(ERROR_FILE_NOT_FOUND) => Ok(None),
-        _ => Err(storage_error(err)),
+        _ => Err(storage_error(operation)(err)),
     }
 }
 
-fn storage_error(err: windows_result::Error) -> Error {
-    Error::StorageError(err.to_string())
+/// Maps registry errors to [`Error::StorageError`] labelled with `operation`, e.g. `set_value`.
+fn storage_error(operation: &'static str) -> impl Fn(windows_result::Error) -> Error {
+    move |err| Error::storage(operation, err)
+}
+
+/// Parses a stored value, ignoring surrounding whitespace and treating an empty or blank one as unset.
+fn parse_id(s: &str) -> Result<Option<DevDeviceId>> {
+    let read_len = s.len();
+    let s = s.trim();
+    if s.is_empty() {
+        return Ok(None);
+    }
+    if s.len() > MAX_ID_LEN {
+        return Err(Error::BadUuidFormat(format!(
+            "stored device ID is longer than {MAX_ID_LEN} bytes ({} bytes), the value may be corrupt",
+            s.len()
+        )));
+    }
+    StoredFormat::decode_stored(s, read_len).map(Some)
 }
 
-fn open_read_key() -> Result<Option<Key>> {
-    reg_options(false)
-        .open(REGISTRY_PATH)
+/// Reads the string value `name` of `key`, returning `None` if it does not exist.
+///
+/// The value is read into a fixed buffer, so that a tampered, oversized value is rejected without being
+/// allocated: a value longer than [`MAX_VALUE_LEN`], or that is not a string, fails with
+/// [`Error::BadUuidFormat`].
+fn get_bounded_string(key: &Key, name: &str) -> Result<Option<String>> {
+    let wide_name: Vec<u16> = name.encode_utf16().chain(std::iter::once(0)).collect();
+    let mut buffer = [0u8; MAX_VALUE_LEN * 2];
+    // SAFETY: `wide_name` is nul-terminated and outlives the call
+    let result = unsafe { key.raw_get_bytes(PCWSTR(wide_name.as_ptr()), &mut buffer) };
+    let (ty, bytes) = match result {
+        Ok(value) => value,
+        Err(err) if err.code() == HRESULT::from(ERROR_MORE_DATA) => {
+            return Err(Error::BadUuidFormat(format!(
+                "registry value {name} is longer than {MAX_VALUE_LEN} characters, the value may be corrupt"
+            )));
+        }
+        Err(err) => return error_not_found_to_none("get_value")(err),
+    };
+    if !matches!(ty, Type::String | Type::ExpandString) {
+        return Err(Error::BadUuidFormat(format!(
+            "registry value {name} is not a string ({ty:?})"
+        )));
+    }
+    let wide: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+        .collect();
+    // the terminating nul is usually, but not always, stored
+    let end = wide
+        .iter()
+        .position(|&unit| unit == 0)
+        .unwrap_or(wide.len());
+    String::from_utf16(&wide[..end])
         .map(Some)
-        .or_else(error_not_found_to_none)
+        .map_err(|e| Error::BadUuidFormat(format!("registry value {name}: {e}")))
 }
 
-fn open_create_key() -> Result<Key> {
-    reg_options(true).open(REGISTRY_PATH).map_err(storage_error)
+pub fn enumerate_apps() -> Result<Vec<(String, Result<DevDeviceId>)>> {
+    RegistryStorage::new()?.enumerate_apps()
 }
 
-pub fn retrieve() -> Result<Option<DevDeviceId>> {
-    let Some(key) = open_read_key()? else {
-        return Ok(None);
+/// MAC addresses are not read on Windows yet, see [`DevDeviceId::from_mac_fallback`].
+#[cfg(feature = "mac-fallback")]
+pub fn mac_address() -> Option<[u8; 6]> {
+    None
+}
+
+/// Does nothing: Windows cannot open a directory as a file, and persists entries with the file.
+pub fn sync_dir(_path: &std::path::Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Clears the read-only attribute of the file at `path`, e.g. after [`FileStorage::seal`](crate::FileStorage::seal).
+pub fn make_writable(path: &std::path::Path) -> std::io::Result<()> {
+    let mut permissions = std::fs::metadata(path)?.permissions();
+    // on Windows this only clears the attribute, rather than making the file writable by everyone
+    #[allow(clippy::permissions_set_readonly_false)]
+    permissions.set_readonly(false);
+    std::fs::set_permissions(path, permissions)
+}
+
+/// The registry is polled, see [`DeviceIdWatcher`](crate::DeviceIdWatcher).
+#[cfg(feature = "watch")]
+pub fn watch(storage: RegistryStorage) -> Result<crate::DeviceIdWatcher> {
+    Ok(crate::DeviceIdWatcher::poll(
+        storage,
+        crate::watch::POLL_INTERVAL,
+    ))
+}
+
+pub fn default_location(storage: &RegistryStorage) -> Result<StorageLocation> {
+    Ok(storage.location())
+}
+
+/// The user's profile directory, `%USERPROFILE%`, see [`StorageLocation::display`].
+pub fn home_dir() -> Option<std::path::PathBuf> {
+    std::env::var_os("USERPROFILE")
+        .map(std::path::PathBuf::from)
+        .filter(|path| path.is_absolute())
+}
+
+/// .NET tooling stores its ID in the registry too, see [`DevDeviceIdBuilder::dotnet_interop`](crate::DevDeviceIdBuilder::dotnet_interop).
+pub fn dotnet_id() -> Option<DevDeviceId> {
+    None
+}
+
+pub fn scan_locations() -> Result<Vec<(StorageLocation, Result<Option<DevDeviceId>>)>> {
+    Ok(scan_registry(LiveRegistry::default()))
+}
+
+/// Reads the default location in both hives and both views of `registry`.
+fn scan_registry(
+    mut registry: impl Registry,
+) -> Vec<(StorageLocation, Result<Option<DevDeviceId>>)> {
+    let mut locations = Vec::new();
+    for scope in [Scope::User, Scope::Machine] {
+        for view_32bit in [false, true] {
+            registry.set_scope(scope);
+            registry.set_view_32bit(view_32bit);
+            let id = registry
+                .get_string(REGISTRY_PATH, REGISTRY_KEY)
+                .and_then(|value| value.map_or(Ok(None), |s| parse_id(&s)));
+            let location = StorageLocation::Registry {
+                key: format!(r"{}\{REGISTRY_PATH}", hive_name(scope)),
+                value: REGISTRY_KEY.to_string(),
+                view_32bit,
+            };
+            locations.push((location, id));
+        }
+    }
+    locations
+}
+
+/// Access to string values in the registry, so that [`RegistryStorage`] can run against a mock.
+///
+/// Keys are paths relative to the hive, e.g. `SOFTWARE\Microsoft\DeveloperTools`.
+pub trait Registry {
+    /// Reads a string value, returning `None` if the key or the value does not exist.
+    ///
+    /// This must not create anything: [`Storage::retrieve`] relies on it being read-only.
+    fn get_string(&self, key: &str, name: &str) -> Result<Option<String>>;
+
+    /// Writes a string value, creating the key if needed.
+    fn set_string(&mut self, key: &str, name: &str, value: &str) -> Result<()>;
+
+    /// Flushes the key to disk.
+    fn flush(&mut self, key: &str) -> Result<()>;
+
+    /// Deletes a value, succeeding if the key or the value does not exist.
+    fn remove_value(&mut self, key: &str, name: &str) -> Result<()>;
+
+    /// Lists the names of the direct subkeys of `key`, e.g. per-application keys, returning an empty list if
+    /// the key does not exist. Defaults to an empty list, for registries that cannot enumerate keys.
+    fn subkeys(&self, key: &str) -> Result<Vec<String>> {
+        let _ = key;
+        Ok(Vec::new())
+    }
+
+    /// Selects the hive that keys are relative to: `HKEY_CURRENT_USER` for [`Scope::User`] (the default)
+    /// or `HKEY_LOCAL_MACHINE` for [`Scope::Machine`]. Defaults to ignoring the scope, for registries
+    /// without hives.
+    fn set_scope(&mut self, scope: Scope) {
+        let _ = scope;
+    }
+
+    /// Selects the registry view that keys are in: the 32-bit one if `view_32bit` is set, otherwise the
+    /// 64-bit one (the default). Defaults to ignoring the view, for registries without views.
+    fn set_view_32bit(&mut self, view_32bit: bool) {
+        let _ = view_32bit;
+    }
+
+    /// Reads the `MachineGuid` identifying the Windows installation, for
+    /// [`RegistryStorage::bind_machine_guid`]. Defaults to `None`, i.e. unknown.
+    fn machine_guid(&self) -> Result<Option<String>> {
+        Ok(None)
+    }
+}
+
+/// The security identifier of the user running this process, e.g. `S-1-5-21-…-1001`.
+fn current_user_sid() -> Option<String> {
+    let mut token = HANDLE::default();
+    // SAFETY: the pseudo handle of the current process needs no closing; `token` is closed below
+    unsafe { OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) }.ok()?;
+    // a `TOKEN_USER` followed by the SID it points to, which is at most 68 bytes
+    let mut buffer = [0u64; 16];
+    let mut len = 0;
+    // SAFETY: the buffer is writable for the given length and aligned for `TOKEN_USER`
+    let info = unsafe {
+        GetTokenInformation(
+            token,
+            TokenUser,
+            Some(buffer.as_mut_ptr().cast()),
+            size_of_val(&buffer) as u32,
+            &mut len,
+        )
     };
-    match key.get_string(REGISTRY_KEY) {
-        Ok(s) => {
-            let uuid =
-                uuid::Uuid::try_parse(&s).map_err(|e| Error::BadUuidFormat(e.to_string()))?;
-            Ok(Some(DevDeviceId(uuid)))
+    // SAFETY: `token` is valid and not used afterwards
+    let _ = unsafe { CloseHandle(token) };
+    info.ok()?;
+    // SAFETY: `GetTokenInformation` wrote a `TOKEN_USER` at the start of the buffer
+    let user = unsafe { &*buffer.as_ptr().cast::<TOKEN_USER>() };
+    let mut sid = PWSTR::null();
+    // SAFETY: the SID is valid while `buffer` is; the string is freed below
+    unsafe { ConvertSidToStringSidW(user.User.Sid, &mut sid) }.ok()?;
+    // SAFETY: `sid` is a nul-terminated wide string allocated with `LocalAlloc`
+    let sid_string = unsafe { sid.to_string() }.ok();
+    unsafe { LocalFree(Some(HLOCAL(sid.0.cast()))) };
+    sid_string
+}
+
+/// Ownership of the named mutex held while checking for and storing a new device ID, so that
+/// of two processes starting together, the second sees the first's ID rather than writing its own.
+struct StoreLock(HANDLE);
+
+impl StoreLock {
+    /// Waits for the mutex of the current user. Returns `None` if it cannot be opened, e.g. when the
+    /// SID cannot be read, in which case storing proceeds unlocked, as it would without the mutex.
+    fn acquire() -> Result<Option<Self>> {
+        let Some(sid) = current_user_sid() else {
+            return Ok(None);
+        };
+        let name = HSTRING::from(format!("{STORE_MUTEX_PREFIX}{sid}"));
+        // SAFETY: `name` is a nul-terminated wide string that outlives the call
+        let Ok(handle) = (unsafe { CreateMutexW(None, false, &name) }) else {
+            return Ok(None);
+        };
+        // SAFETY: `handle` is a valid mutex handle, closed below or by the returned lock
+        let wait = unsafe { WaitForSingleObject(handle, STORE_MUTEX_TIMEOUT.as_millis() as u32) };
+        // an abandoned mutex is ours now: its previous owner exited, and a registry value
+        // is either fully written or not at all, so there is nothing to recover
+        if wait == WAIT_OBJECT_0 || wait == WAIT_ABANDONED {
+            return Ok(Some(Self(handle)));
         }
-        Err(err) => error_not_found_to_none(err),
+        let err = if wait == WAIT_TIMEOUT {
+            Error::Timeout(STORE_MUTEX_TIMEOUT)
+        } else {
+            Error::storage("wait_mutex", windows_result::Error::from_thread())
+        };
+        // SAFETY: `handle` is valid and not used afterwards
+        let _ = unsafe { CloseHandle(handle) };
+        Err(err)
+    }
+}
+
+impl Drop for StoreLock {
+    fn drop(&mut self) {
+        // SAFETY: this thread owns the mutex, and the handle is not used afterwards
+        unsafe {
+            let _ = ReleaseMutex(self.0);
+            let _ = CloseHandle(self.0);
+        }
+    }
+}
+
+/// The real registry: `HKEY_CURRENT_USER`, or `HKEY_LOCAL_MACHINE` for [`Scope::Machine`], in the 64-bit view
+/// unless [`Registry::set_view_32bit`] selects the 32-bit one.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LiveRegistry {
+    scope: Scope,
+    view_32bit: bool,
+}
+
+impl LiveRegistry {
+    /// The real registry, with keys relative to the hive of `scope`.
+    pub fn new(scope: Scope) -> Self {
+        Self {
+            scope,
+            view_32bit: false,
+        }
+    }
+}
+
+impl Registry for LiveRegistry {
+    fn get_string(&self, key: &str, name: &str) -> Result<Option<String>> {
+        // read access only (`KEY_READ`), without the create flag
+        let Some(key) = reg_options(self.scope, self.view_32bit, false)
+            .open(key)
+            .map(Some)
+            .or_else(error_not_found_to_none("open_key"))?
+        else {
+            return Ok(None);
+        };
+        get_bounded_string(&key, name)
+    }
+
+    fn set_string(&mut self, key: &str, name: &str, value: &str) -> Result<()> {
+        let key = reg_options(self.scope, self.view_32bit, true)
+            .open(key)
+            .map_err(storage_error("create_subkey"))?;
+        key.set_string(name, value)
+            .map_err(storage_error("set_value"))
+    }
+
+    fn flush(&mut self, key: &str) -> Result<()> {
+        let key = reg_options(self.scope, self.view_32bit, false)
+            .open(key)
+            .map_err(storage_error("open_key"))?;
+        // SAFETY: the handle is owned by `key`, which outlives the call
+        unsafe { RegFlushKey(HKEY(key.as_raw())) }
+            .ok()
+            .map_err(storage_error("flush_key"))
+    }
+
+    fn remove_value(&mut self, key: &str, name: &str) -> Result<()> {
+        let mut options = reg_options(self.scope, self.view_32bit, false);
+        options.write();
+        let Some(key) = options
+            .open(key)
+            .map(Some)
+            .or_else(error_not_found_to_none("open_key"))?
+        else {
+            return Ok(());
+        };
+        key.remove_value(name)
+            .map(Some)
+            .or_else(error_not_found_to_none("delete_value"))
+            .map(|_: Option<()>| ())
+    }
+
+    fn subkeys(&self, key: &str) -> Result<Vec<String>> {
+        let Some(key) = reg_options(self.scope, self.view_32bit, false)
+            .open(key)
+            .map(Some)
+            .or_else(error_not_found_to_none("open_key"))?
+        else {
+            return Ok(Vec::new());
+        };
+        Ok(key.keys().map_err(storage_error("enum_keys"))?.collect())
+    }
+
+    fn set_scope(&mut self, scope: Scope) {
+        self.scope = scope;
+    }
+
+    fn set_view_32bit(&mut self, view_32bit: bool) {
+        self.view_32bit = view_32bit;
+    }
+
+    fn machine_guid(&self) -> Result<Option<String>> {
+        let Some(key) = LOCAL_MACHINE
+            .options()
+            .read()
+            .access(KEY_WOW64_64KEY.0)
+            .open(MACHINE_GUID_PATH)
+            .map(Some)
+            .or_else(error_not_found_to_none("open_key"))?
+        else {
+            return Ok(None);
+        };
+        get_bounded_string(&key, MACHINE_GUID_NAME)
+    }
+}
+
+/// An in-memory [`Registry`] for tests, so they don't touch the machine registry.
+///
+/// Clones share the same values, so a test can keep a handle to inspect what a storage wrote.
+/// Each [`Scope`] has its own fake hive, and each hive its own 32-bit view, so machine-scope behavior can
+/// be tested without elevation.
+#[cfg(any(test, feature = "test-util"))]
+#[derive(Debug, Clone, Default)]
+pub struct MockWindowsRegistry {
+    values: std::sync::Arc<std::sync::Mutex<std::collections::HashMap<MockValueKey, String>>>,
+    machine_guid: std::sync::Arc<std::sync::Mutex<Option<String>>>,
+    scope: Scope,
+    view_32bit: bool,
+}
+
+/// Hive, view (whether 32-bit), key and value name.
+#[cfg(any(test, feature = "test-util"))]
+type MockValueKey = (Scope, bool, String, String);
+
+#[cfg(any(test, feature = "test-util"))]
+impl MockWindowsRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a registry that already holds `id` at the default location.
+    pub fn seeded(id: &DevDeviceId) -> Self {
+        Self::with_value(REGISTRY_PATH, REGISTRY_KEY, &id.to_string())
+    }
+
+    /// Creates a registry holding an arbitrary string value, e.g. a malformed ID.
+    pub fn with_value(key: &str, name: &str, value: &str) -> Self {
+        let mut registry = Self::new();
+        registry.set_string(key, name, value).unwrap();
+        registry
+    }
+
+    /// Returns the string value `name` under `key`, if any.
+    pub fn value(&self, key: &str, name: &str) -> Option<String> {
+        self.get_string(key, name).unwrap()
+    }
+
+    /// Returns a clone sharing the same values, with keys relative to the hive of `scope`,
+    /// e.g. to inspect what a [`Scope::Machine`] storage wrote.
+    pub fn scoped(&self, scope: Scope) -> Self {
+        let mut registry = self.clone();
+        registry.scope = scope;
+        registry
+    }
+
+    fn value_key(&self, key: &str, name: &str) -> MockValueKey {
+        (
+            self.scope,
+            self.view_32bit,
+            key.to_string(),
+            name.to_string(),
+        )
+    }
+
+    /// Sets the `MachineGuid` of the simulated installation, e.g. to simulate a reinstall.
+    pub fn set_machine_guid(&self, guid: Option<&str>) {
+        *self.machine_guid.lock().unwrap() = guid.map(str::to_string);
     }
 }
 
-pub fn store(id: &DevDeviceId) -> Result<()> {
-    let key = open_create_key()?;
-    let s = id.to_string();
-    key.set_string(REGISTRY_KEY, &s).map_err(storage_error)
+#[cfg(any(test, feature = "test-util"))]
+impl Registry for MockWindowsRegistry {
+    fn get_string(&self, key: &str, name: &str) -> Result<Option<String>> {
+        let values = self.values.lock().unwrap();
+        Ok(values.get(&self.value_key(key, name)).cloned())
+    }
+
+    fn set_string(&mut self, key: &str, name: &str, value: &str) -> Result<()> {
+        let mut values = self.values.lock().unwrap();
+        values.insert(self.value_key(key, name), value.to_string());
+        Ok(())
+    }
+
+    fn flush(&mut self, _key: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn remove_value(&mut self, key: &str, name: &str) -> Result<()> {
+        let mut values = self.values.lock().unwrap();
+        values.remove(&self.value_key(key, name));
+        Ok(())
+    }
+
+    fn subkeys(&self, key: &str) -> Result<Vec<String>> {
+        let prefix = format!(r"{key}\");
+        let values = self.values.lock().unwrap();
+        let mut subkeys: Vec<String> = values
+            .keys()
+            .filter(|(scope, view_32bit, _, _)| {
+                *scope == self.scope && *view_32bit == self.view_32bit
+            })
+            .filter_map(|(_, _, path, _)| path.strip_prefix(&prefix))
+            .map(|rest| rest.split('\\').next().unwrap_or_default().to_string())
+            .collect();
+        subkeys.sort();
+        subkeys.dedup();
+        Ok(subkeys)
+    }
+
+    fn set_scope(&mut self, scope: Scope) {
+        self.scope = scope;
+    }
+
+    fn set_view_32bit(&mut self, view_32bit: bool) {
+        self.view_32bit = view_32bit;
+    }
+
+    fn machine_guid(&self) -> Result<Option<String>> {
+        Ok(self.machine_guid.lock().unwrap().clone())
+    }
+}
+
+/// Stores the device ID in the registry, as the `deviceid` value of
+/// `HKEY_CURRENT_USER\SOFTWARE\Microsoft\DeveloperTools`, or under `HKEY_LOCAL_MACHINE` for [`Scope::Machine`].
+#[derive(Debug, Clone)]
+pub struct RegistryStorage<R = LiveRegistry> {
+    registry: R,
+    key: String,
+    format: StoredFormat,
+    record_metadata: bool,
+    durable: bool,
+    read_only: bool,
+    io_timeout: Option<Duration>,
+    bind_machine_guid: bool,
+    scope: Scope,
+    clock: Arc<dyn Clock>,
+}
+
+impl RegistryStorage {
+    /// Creates a storage for the default registry location.
+    pub fn new() -> Result<Self> {
+        Ok(Self::with_registry(LiveRegistry::default()))
+    }
+
+    pub(crate) fn configured(builder: &DevDeviceIdBuilder) -> Result<Self> {
+        Ok(Self::new()?
+            .stored_format(builder.stored_format)
+            .record_metadata(builder.record_metadata)
+            .durable(builder.durable)
+            .read_only(builder.strict_readonly)
+            .registry_path(builder.registry_path.as_deref())
+            .app_name(builder.app_name.as_deref())
+            .io_timeout(builder.io_timeout)
+            .bind_machine_guid(builder.bind_machine_guid)
+            .scope(builder.scope)
+            .shared_clock(builder.clock.clone()))
+    }
+}
+
+impl<R: Registry> RegistryStorage<R> {
+    /// Creates a storage for the default location within the given registry, e.g. a `MockWindowsRegistry`.
+    pub fn with_registry(registry: R) -> Self {
+        Self {
+            registry,
+            key: REGISTRY_PATH.to_string(),
+            format: StoredFormat::default(),
+            record_metadata: false,
+            durable: false,
+            read_only: false,
+            io_timeout: None,
+            bind_machine_guid: false,
+            scope: Scope::User,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Sets the clock timestamping the [`Metadata`]. Defaults to [`SystemClock`].
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    fn shared_clock(mut self, clock: Option<Arc<dyn Clock>>) -> Self {
+        if let Some(clock) = clock {
+            self.clock = clock;
+        }
+        self
+    }
+
+    /// Sets the form written by [`Storage::store`]. Defaults to [`StoredFormat::Hyphenated`].
+    pub fn stored_format(mut self, format: StoredFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Writes [`Metadata`] to a sibling `deviceid_meta` value when storing. Defaults to `false`.
+    pub fn record_metadata(mut self, record: bool) -> Self {
+        self.record_metadata = record;
+        self
+    }
+
+    /// Flushes the registry key to disk after storing. Defaults to `false`.
+    /// See [`DevDeviceIdBuilder::durable`].
+    pub fn durable(mut self, durable: bool) -> Self {
+        self.durable = durable;
+        self
+    }
+
+    /// Fails every write with [`Error::ReadOnly`], so that no key or value is ever created or removed.
+    /// Defaults to `false`. See [`DevDeviceIdBuilder::strict_readonly`].
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Nests the key under a per-application subkey, e.g. `SOFTWARE\Microsoft\DeveloperTools\<app>`.
+    pub(crate) fn app_name(mut self, app_name: Option<&str>) -> Self {
+        if let Some(app_name) = app_name {
+            self.key = format!(r"{}\{app_name}", self.key);
+        }
+        self
+    }
+
+    /// Replaces the key `SOFTWARE\Microsoft\DeveloperTools`. See [`DevDeviceIdBuilder::registry_path`].
+    pub(crate) fn registry_path(mut self, path: Option<&str>) -> Self {
+        if let Some(path) = path {
+            self.key = path.to_string();
+        }
+        self
+    }
+
+    /// Bounds [`Storage`] operations by `timeout`. See [`DevDeviceIdBuilder::io_timeout`].
+    ///
+    /// The registry is cloned onto a worker thread, so clones must share the underlying registry,
+    /// as [`LiveRegistry`] and `MockWindowsRegistry` do.
+    pub fn io_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.io_timeout = timeout;
+        self
+    }
+
+    /// Stores the device ID in the hive of `scope`. Defaults to [`Scope::User`].
+    /// See [`DevDeviceIdBuilder::scope`].
+    pub fn scope(mut self, scope: Scope) -> Self {
+        self.registry.set_scope(scope);
+        self.scope = scope;
+        self
+    }
+
+    /// The location of the registry value, for reports.
+    pub fn location(&self) -> StorageLocation {
+        StorageLocation::Registry {
+            key: format!(r"{}\{}", hive_name(self.scope), self.key),
+            value: REGISTRY_KEY.to_string(),
+            view_32bit: false,
+        }
+    }
+
+    /// Records the `MachineGuid` of the Windows installation next to the device ID when storing, and
+    /// treats the stored ID as absent once the installation's `MachineGuid` differs, e.g. after
+    /// the OS was reinstalled with a roaming profile. Defaults to `false`.
+    /// See [`DevDeviceIdBuilder::bind_machine_guid`].
+    ///
+    /// An ID stored without a recorded `MachineGuid` is kept.
+    pub fn bind_machine_guid(mut self, bind: bool) -> Self {
+        self.bind_machine_guid = bind;
+        self
+    }
+
+    /// Reads the [`Metadata`] value next to the device ID, returning `None` if it is absent or malformed.
+    pub fn metadata(&self) -> Result<Option<Metadata>> {
+        let json = self.registry.get_string(&self.key, REGISTRY_METADATA_KEY)?;
+        Ok(json.as_deref().and_then(Metadata::from_json))
+    }
+
+    /// Checks that the device ID can be read and stored, without storing one: the stored ID, if any,
+    /// must be readable, and a probe value is written next to it and removed.
+    ///
+    /// The key is created if it does not exist, as storing would.
+    pub fn preflight(&mut self) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        self.retrieve_id()?;
+        self.registry
+            .set_string(&self.key, REGISTRY_PREFLIGHT_KEY, "")?;
+        self.registry
+            .remove_value(&self.key, REGISTRY_PREFLIGHT_KEY)
+    }
+
+    /// Writes a marker value next to the device ID, so that [`Storage::overwrite`] and [`Storage::clear`]
+    /// fail with [`Error::Sealed`]. See [`DevDeviceId::seal`].
+    pub fn seal(&mut self) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        if self.retrieve_id()?.is_none() {
+            return Err(Error::storage("seal", "no device ID is stored"));
+        }
+        self.registry
+            .set_string(&self.key, REGISTRY_SEALED_KEY, "1")?;
+        self.registry.flush(&self.key)
+    }
+
+    /// Undoes [`RegistryStorage::seal`] by removing the marker value. Does nothing if the ID is not
+    /// sealed. See [`DevDeviceId::unseal`].
+    pub fn unseal(&mut self) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        self.registry.remove_value(&self.key, REGISTRY_SEALED_KEY)?;
+        self.registry.flush(&self.key)
+    }
+
+    /// Reports whether the marker value written by [`RegistryStorage::seal`] is present.
+    pub fn is_sealed(&self) -> Result<bool> {
+        Ok(self
+            .registry
+            .get_string(&self.key, REGISTRY_SEALED_KEY)?
+            .is_some())
+    }
+
+    /// The device IDs stored in the per-application subkeys of the key, by application name.
+    pub(crate) fn enumerate_apps(&self) -> Result<Vec<(String, Result<DevDeviceId>)>> {
+        let mut apps = Vec::new();
+        for name in self.registry.subkeys(&self.key)? {
+            let key = format!(r"{}\{name}", self.key);
+            match self.registry.get_string(&key, REGISTRY_KEY) {
+                Ok(Some(value)) => match parse_id(&value) {
+                    Ok(Some(id)) => apps.push((name, Ok(id))),
+                    Ok(None) => {}
+                    Err(err) => apps.push((name, Err(err))),
+                },
+                Ok(None) => {}
+                Err(err) => apps.push((name, Err(err))),
+            }
+        }
+        apps.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(apps)
+    }
+
+    fn retrieve_id(&mut self) -> Result<Option<DevDeviceId>> {
+        self.stored_value()?.map_or(Ok(None), |s| parse_id(&s))
+    }
+
+    /// The stored value of the device ID, or `None` if it is bound to another `MachineGuid`.
+    fn stored_value(&self) -> Result<Option<String>> {
+        let value = self.registry.get_string(&self.key, REGISTRY_KEY)?;
+        if value.is_none() || !self.bind_machine_guid {
+            return Ok(value);
+        }
+        let recorded = self
+            .registry
+            .get_string(&self.key, REGISTRY_MACHINE_GUID_KEY)?;
+        match (recorded, self.registry.machine_guid()?) {
+            (Some(recorded), Some(current)) if !recorded.eq_ignore_ascii_case(&current) => Ok(None),
+            _ => Ok(value),
+        }
+    }
+
+    fn write_id(&mut self, id: &DevDeviceId, overwrite: bool) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        // storing over a sealed ID fails with `AlreadySet` below, as for any stored ID
+        if overwrite && self.is_sealed()? {
+            return Err(Error::Sealed);
+        }
+        // held until the new ID is written, so the check and the write are atomic
+        let _lock = if overwrite {
+            None
+        } else {
+            StoreLock::acquire()?
+        };
+        let stored = self.stored_value()?;
+        // a blank value is unset, as when reading it
+        if !overwrite && stored.is_some_and(|s| !s.trim().is_empty()) {
+            return Err(Error::AlreadySet);
+        }
+        let s = self.format.encode(id);
+        self.registry.set_string(&self.key, REGISTRY_KEY, &s)?;
+        if self.bind_machine_guid {
+            match self.registry.machine_guid()? {
+                Some(guid) => {
+                    self.registry
+                        .set_string(&self.key, REGISTRY_MACHINE_GUID_KEY, &guid)?
+                }
+                // nothing to compare against later, so the ID stays valid
+                None => self
+                    .registry
+                    .remove_value(&self.key, REGISTRY_MACHINE_GUID_KEY)?,
+            }
+        }
+        if self.record_metadata {
+            // best-effort: the ID is already stored, and metadata is only informational
+            let json = Metadata::at(self.clock.now()).to_json();
+            let _ = self
+                .registry
+                .set_string(&self.key, REGISTRY_METADATA_KEY, &json);
+        }
+        if self.durable {
+            self.registry.flush(&self.key)?;
+        }
+        Ok(())
+    }
+}
+
+impl<R: Registry + Clone + Send + 'static> RegistryStorage<R> {
+    /// Runs `op` directly, or on a worker thread bounded by the `io_timeout` if one is set.
+    fn bounded<T: Send + 'static>(
+        &mut self,
+        op: impl FnOnce(&mut Self) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let Some(timeout) = self.io_timeout else {
+            return op(self);
+        };
+        let mut worker = self.clone();
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            // the receiver is gone if we already timed out
+            let _ = tx.send(op(&mut worker));
+        });
+        match rx.recv_timeout(timeout) {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => Err(Error::Timeout(timeout)),
+            Err(RecvTimeoutError::Disconnected) => {
+                Err(Error::storage("registry", "worker thread panicked"))
+            }
+        }
+    }
+}
+
+impl<R: Registry + Clone + Send + 'static> Storage for RegistryStorage<R> {
+    fn retrieve(&mut self) -> Result<Option<DevDeviceId>> {
+        self.bounded(|storage| storage.retrieve_id())
+    }
+
+    fn store(&mut self, id: &DevDeviceId) -> Result<()> {
+        let id = id.clone();
+        self.bounded(move |storage| storage.write_id(&id, false))
+    }
+
+    fn overwrite(&mut self, id: &DevDeviceId) -> Result<()> {
+        let id = id.clone();
+        self.bounded(move |storage| storage.write_id(&id, true))
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        self.bounded(|storage| {
+            if storage.is_sealed()? {
+                return Err(Error::Sealed);
+            }
+            storage.registry.remove_value(&storage.key, REGISTRY_KEY)?;
+            // best-effort, like writing it
+            let _ = storage
+                .registry
+                .remove_value(&storage.key, REGISTRY_METADATA_KEY);
+            let _ = storage
+                .registry
+                .remove_value(&storage.key, REGISTRY_MACHINE_GUID_KEY);
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_registry_stored_format() {
+        let registry = MockWindowsRegistry::new();
+        let mut storage =
+            RegistryStorage::with_registry(registry.clone()).stored_format(StoredFormat::Simple);
+        let id = DevDeviceId::generate();
+        storage.store(&id).unwrap();
+        assert_eq!(
+            registry.value(REGISTRY_PATH, REGISTRY_KEY),
+            Some(StoredFormat::Simple.encode(&id))
+        );
+        assert_eq!(storage.retrieve().unwrap(), Some(id));
+    }
+
+    #[test]
+    fn test_seal() {
+        let registry = MockWindowsRegistry::new();
+        let mut storage = RegistryStorage::with_registry(registry.clone());
+        assert!(matches!(storage.seal(), Err(Error::StorageError(_))));
+        let id = DevDeviceId::generate();
+        storage.store(&id).unwrap();
+        assert!(!storage.is_sealed().unwrap());
+
+        storage.seal().unwrap();
+        assert!(storage.is_sealed().unwrap());
+        assert_eq!(
+            registry.value(REGISTRY_PATH, REGISTRY_SEALED_KEY),
+            Some("1".to_string())
+        );
+        assert!(matches!(
+            storage.overwrite(&DevDeviceId::generate()),
+            Err(Error::Sealed)
+        ));
+        assert!(matches!(storage.clear(), Err(Error::Sealed)));
+        assert_eq!(storage.retrieve().unwrap(), Some(id));
+
+        storage.unseal().unwrap();
+        assert!(!storage.is_sealed().unwrap());
+        assert_eq!(registry.value(REGISTRY_PATH, REGISTRY_SEALED_KEY), None);
+        storage.clear().unwrap();
+        assert_eq!(storage.retrieve().unwrap(), None);
+    }
+
+    #[test]
+    fn test_mock_registry_metadata() {
+        let registry = MockWindowsRegistry::new();
+        let mut storage = RegistryStorage::with_registry(registry.clone()).record_metadata(true);
+        let id = DevDeviceId::generate();
+        storage.store(&id).unwrap();
+        assert!(!storage.metadata().unwrap().unwrap().is_other_version());
+
+        let mut storage = RegistryStorage::with_registry(MockWindowsRegistry::seeded(&id));
+        assert_eq!(storage.metadata().unwrap(), None);
+        assert_eq!(storage.retrieve().unwrap(), Some(id));
+    }
+
+    #[test]
+    fn test_app_name_isolation() {
+        let registry = MockWindowsRegistry::new();
+        let mut default = RegistryStorage::with_registry(registry.clone());
+        let mut app_a = RegistryStorage::with_registry(registry.clone()).app_name(Some("app-a"));
+        let mut app_b = RegistryStorage::with_registry(registry.clone()).app_name(Some("app-b"));
+
+        let id = DevDeviceId::get_or_generate_in(&mut default).unwrap();
+        let id_a = DevDeviceId::get_or_generate_in(&mut app_a).unwrap();
+        let id_b = DevDeviceId::get_or_generate_in(&mut app_b).unwrap();
+        assert_ne!(id, id_a);
+        assert_ne!(id_a, id_b);
+        assert_eq!(
+            registry.value(REGISTRY_PATH, REGISTRY_KEY),
+            Some(id.to_string())
+        );
+        assert_eq!(
+            registry.value(r"SOFTWARE\Microsoft\DeveloperTools\app-a", REGISTRY_KEY),
+            Some(id_a.to_string())
+        );
+    }
+
+    /// A registry whose every access stalls, like one backed by an unreachable roaming profile.
+    #[derive(Clone)]
+    struct SlowRegistry(MockWindowsRegistry, Duration);
+
+    impl Registry for SlowRegistry {
+        fn get_string(&self, key: &str, name: &str) -> Result<Option<String>> {
+            std::thread::sleep(self.1);
+            self.0.get_string(key, name)
+        }
+
+        fn set_string(&mut self, key: &str, name: &str, value: &str) -> Result<()> {
+            std::thread::sleep(self.1);
+            self.0.set_string(key, name, value)
+        }
+
+        fn flush(&mut self, key: &str) -> Result<()> {
+            self.0.flush(key)
+        }
+
+        fn remove_value(&mut self, key: &str, name: &str) -> Result<()> {
+            std::thread::sleep(self.1);
+            self.0.remove_value(key, name)
+        }
+    }
+
+    #[test]
+    fn test_io_timeout_exceeded() {
+        let registry = SlowRegistry(MockWindowsRegistry::new(), Duration::from_secs(2));
+        let timeout = Duration::from_millis(50);
+        let mut storage = RegistryStorage::with_registry(registry).io_timeout(Some(timeout));
+        let start = std::time::Instant::now();
+        assert!(matches!(storage.retrieve(), Err(Error::Timeout(t)) if t == timeout));
+        assert!(matches!(
+            storage.store(&DevDeviceId::generate()),
+            Err(Error::Timeout(_))
+        ));
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_io_timeout_within_limit() {
+        let registry = SlowRegistry(MockWindowsRegistry::new(), Duration::from_millis(1));
+        let mut storage =
+            RegistryStorage::with_registry(registry).io_timeout(Some(Duration::from_secs(5)));
+        let id = DevDeviceId::get_or_generate_in(&mut storage).unwrap();
+        assert_eq!(storage.retrieve().unwrap(), Some(id));
+    }
+
+    #[test]
+    fn test_get_repairing_corrupt_value() {
+        let registry = MockWindowsRegistry::with_value(REGISTRY_PATH, REGISTRY_KEY, "not-a-uuid");
+        let mut storage = RegistryStorage::with_registry(registry.clone());
+        assert!(matches!(storage.retrieve(), Err(Error::BadUuidFormat(_))));
+        assert!(matches!(storage.retrieve(), Err(Error::BadUuidFormat(_))));
+
+        assert_eq!(DevDeviceId::get_repairing_in(&mut storage).unwrap(), None);
+        assert_eq!(registry.value(REGISTRY_PATH, REGISTRY_KEY), None);
+        let id = DevDeviceId::get_or_generate_in(&mut storage).unwrap();
+        assert_eq!(
+            DevDeviceId::get_repairing_in(&mut storage).unwrap(),
+            Some(id)
+        );
+    }
+
+    #[test]
+    fn test_strict_readonly() {
+        let registry = MockWindowsRegistry::new();
+        let mut storage = RegistryStorage::with_registry(registry.clone()).read_only(true);
+        assert_eq!(storage.retrieve().unwrap(), None);
+        let result = DevDeviceId::get_or_generate_in(&mut storage);
+        assert!(matches!(result, Err(Error::ReadOnly)));
+        assert_eq!(registry.value(REGISTRY_PATH, REGISTRY_KEY), None);
+
+        // a corrupt value is reported as absent, but left in place
+        let registry = MockWindowsRegistry::with_value(REGISTRY_PATH, REGISTRY_KEY, "not-a-uuid");
+        let mut storage = RegistryStorage::with_registry(registry.clone()).read_only(true);
+        assert_eq!(DevDeviceId::get_repairing_in(&mut storage).unwrap(), None);
+        assert_eq!(
+            registry.value(REGISTRY_PATH, REGISTRY_KEY),
+            Some("not-a-uuid".to_string())
+        );
+    }
+
+    #[test]
+    fn test_custom_registry_path() {
+        let registry = MockWindowsRegistry::new();
+        let mut storage = RegistryStorage::with_registry(registry.clone())
+            .registry_path(Some(r"SOFTWARE\Contoso\Tenant1"));
+        let id = DevDeviceId::get_or_generate_in(&mut storage).unwrap();
+        assert_eq!(
+            registry.value(r"SOFTWARE\Contoso\Tenant1", REGISTRY_KEY),
+            Some(id.to_string())
+        );
+        assert_eq!(registry.value(REGISTRY_PATH, REGISTRY_KEY), None);
+
+        let mut app = RegistryStorage::with_registry(registry.clone())
+            .registry_path(Some(r"SOFTWARE\Contoso\Tenant1"))
+            .app_name(Some("app"));
+        let app_id = DevDeviceId::get_or_generate_in(&mut app).unwrap();
+        assert_eq!(
+            registry.value(r"SOFTWARE\Contoso\Tenant1\app", REGISTRY_KEY),
+            Some(app_id.to_string())
+        );
+    }
+
+    #[test]
+    fn test_mock_registry_malformed_value() {
+        let registry = MockWindowsRegistry::with_value(REGISTRY_PATH, REGISTRY_KEY, "garbage");
+        let result = RegistryStorage::with_registry(registry).retrieve();
+        assert!(matches!(result, Err(Error::BadUuidFormat(_))));
+    }
+
+    #[test]
+    fn test_mock_registry_oversized_value() {
+        let id = DevDeviceId::generate();
+        let padded = format!("  {}\t", StoredFormat::Checked.encode(&id));
+        let registry = MockWindowsRegistry::with_value(REGISTRY_PATH, REGISTRY_KEY, &padded);
+        let result = RegistryStorage::with_registry(registry).retrieve();
+        assert_eq!(result.unwrap(), Some(id.clone()));
+
+        let oversized = format!("{id}{}", "0".repeat(1 << 20));
+        let registry = MockWindowsRegistry::with_value(REGISTRY_PATH, REGISTRY_KEY, &oversized);
+        let result = RegistryStorage::with_registry(registry).retrieve();
+        assert!(
+            matches!(&result, Err(Error::BadUuidFormat(msg)) if msg.contains("longer than")),
+            "{result:?}"
+        );
+    }
+
+    #[test]
+    fn test_mock_registry_truncated_value_reports_length() {
+        let id = DevDeviceId::generate().to_string();
+        let truncated = format!("{} ", &id[..35]);
+        let registry = MockWindowsRegistry::with_value(REGISTRY_PATH, REGISTRY_KEY, &truncated);
+        let result = RegistryStorage::with_registry(registry).retrieve();
+        assert!(
+            matches!(&result, Err(Error::BadUuidFormat(msg)) if msg.contains("(read 36 bytes, 35 after trimming)")),
+            "{result:?}"
+        );
+    }
+
+    #[test]
+    fn test_concurrent_get_or_generate_converges() {
+        let registry = MockWindowsRegistry::new();
+        let barrier = std::sync::Arc::new(std::sync::Barrier::new(8));
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let registry = registry.clone();
+                let barrier = barrier.clone();
+                std::thread::spawn(move || {
+                    let mut storage = RegistryStorage::with_registry(registry);
+                    barrier.wait();
+                    DevDeviceId::get_or_generate_in(&mut storage).unwrap()
+                })
+            })
+            .collect();
+        let ids: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+        assert!(ids.iter().all(|id| *id == ids[0]));
+        assert_eq!(
+            registry.value(REGISTRY_PATH, REGISTRY_KEY),
+            Some(ids[0].to_string())
+        );
+    }
+
+    #[test]
+    fn test_store_lock_is_per_user() {
+        let sid = current_user_sid().unwrap();
+        assert!(sid.starts_with("S-1-"), "{sid}");
+        let lock = StoreLock::acquire().unwrap().unwrap();
+        drop(lock);
+        // released, so it can be taken again
+        assert!(StoreLock::acquire().unwrap().is_some());
+    }
+
+    #[test]
+    fn test_bind_machine_guid() {
+        let registry = MockWindowsRegistry::new();
+        registry.set_machine_guid(Some("8a3c4f0e-0000-4000-8000-000000000001"));
+        let mut storage = RegistryStorage::with_registry(registry.clone()).bind_machine_guid(true);
+        let id = DevDeviceId::get_or_generate_in(&mut storage).unwrap();
+        assert_eq!(
+            registry.value(REGISTRY_PATH, REGISTRY_MACHINE_GUID_KEY),
+            Some("8a3c4f0e-0000-4000-8000-000000000001".to_string())
+        );
+        assert_eq!(DevDeviceId::get_or_generate_in(&mut storage).unwrap(), id);
+
+        // the OS was reinstalled
+        registry.set_machine_guid(Some("8a3c4f0e-0000-4000-8000-000000000002"));
+        assert_eq!(storage.retrieve().unwrap(), None);
+        let new_id = DevDeviceId::get_or_generate_in(&mut storage).unwrap();
+        assert_ne!(new_id, id);
+        assert_eq!(
+            registry.value(REGISTRY_PATH, REGISTRY_MACHINE_GUID_KEY),
+            Some("8a3c4f0e-0000-4000-8000-000000000002".to_string())
+        );
+        assert_eq!(storage.retrieve().unwrap(), Some(new_id.clone()));
+
+        // without binding, the stored ID is used regardless
+        registry.set_machine_guid(Some("8a3c4f0e-0000-4000-8000-000000000003"));
+        let mut unbound = RegistryStorage::with_registry(registry.clone());
+        assert_eq!(unbound.retrieve().unwrap(), Some(new_id));
+    }
+
+    #[test]
+    fn test_bind_machine_guid_keeps_unbound_id() {
+        let id = DevDeviceId::generate();
+        let registry = MockWindowsRegistry::seeded(&id);
+        registry.set_machine_guid(Some("8a3c4f0e-0000-4000-8000-000000000001"));
+        let mut storage = RegistryStorage::with_registry(registry).bind_machine_guid(true);
+        assert_eq!(storage.retrieve().unwrap(), Some(id));
+    }
+
+    #[test]
+    fn test_enumerate_apps() {
+        let registry = MockWindowsRegistry::new();
+        let storage = RegistryStorage::with_registry(registry.clone());
+        assert!(storage.enumerate_apps().unwrap().is_empty());
+
+        DevDeviceId::get_or_generate_in(&mut storage.clone()).unwrap();
+        let mut ids = Vec::new();
+        for app in ["beta", "alpha"] {
+            let mut app_storage =
+                RegistryStorage::with_registry(registry.clone()).app_name(Some(app));
+            ids.push(DevDeviceId::get_or_generate_in(&mut app_storage).unwrap());
+        }
+        let mut registry = registry;
+        registry
+            .set_string(
+                &format!(r"{REGISTRY_PATH}\corrupt"),
+                REGISTRY_KEY,
+                "garbage",
+            )
+            .unwrap();
+        registry
+            .set_string(&format!(r"{REGISTRY_PATH}\other"), "unrelated", "value")
+            .unwrap();
+
+        let apps = storage.enumerate_apps().unwrap();
+        let names: Vec<_> = apps.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["alpha", "beta", "corrupt"]);
+        assert_eq!(apps[0].1.as_ref().unwrap(), &ids[1]);
+        assert_eq!(apps[1].1.as_ref().unwrap(), &ids[0]);
+        assert!(matches!(apps[2].1, Err(Error::BadUuidFormat(_))));
+    }
+
+    #[test]
+    fn test_location() {
+        let storage =
+            RegistryStorage::with_registry(MockWindowsRegistry::new()).app_name(Some("app"));
+        assert_eq!(
+            storage.location(),
+            StorageLocation::Registry {
+                key: r"HKEY_CURRENT_USER\SOFTWARE\Microsoft\DeveloperTools\app".to_string(),
+                value: REGISTRY_KEY.to_string(),
+                view_32bit: false,
+            }
+        );
+        let storage = storage.scope(Scope::Machine);
+        assert!(matches!(
+            storage.location(),
+            StorageLocation::Registry { key, .. } if key.starts_with(r"HKEY_LOCAL_MACHINE\")
+        ));
+    }
+
+    #[test]
+    fn test_machine_scope() {
+        let registry = MockWindowsRegistry::new();
+        let machine = registry.scoped(Scope::Machine);
+        let mut storage = RegistryStorage::with_registry(registry.clone()).scope(Scope::Machine);
+        let id = DevDeviceId::get_or_generate_in(&mut storage).unwrap();
+        assert_eq!(
+            machine.value(REGISTRY_PATH, REGISTRY_KEY),
+            Some(id.to_string())
+        );
+        assert_eq!(registry.value(REGISTRY_PATH, REGISTRY_KEY), None);
+
+        // the user scope keeps its own ID
+        let mut user = RegistryStorage::with_registry(registry.clone());
+        let user_id = DevDeviceId::get_or_generate_in(&mut user).unwrap();
+        assert_ne!(user_id, id);
+
+        // every storage of the machine scope shares its ID
+        let mut other = RegistryStorage::with_registry(registry.clone()).scope(Scope::Machine);
+        assert_eq!(other.retrieve().unwrap(), Some(id.clone()));
+        other.clear().unwrap();
+        assert_eq!(machine.value(REGISTRY_PATH, REGISTRY_KEY), None);
+        assert_eq!(user.retrieve().unwrap(), Some(user_id));
+    }
+
+    #[test]
+    fn test_preflight() {
+        let registry = MockWindowsRegistry::new();
+        RegistryStorage::with_registry(registry.clone())
+            .preflight()
+            .unwrap();
+        assert_eq!(registry.value(REGISTRY_PATH, REGISTRY_KEY), None);
+        assert_eq!(registry.value(REGISTRY_PATH, REGISTRY_PREFLIGHT_KEY), None);
+
+        let mut storage = RegistryStorage::with_registry(registry.clone()).read_only(true);
+        assert!(matches!(storage.preflight(), Err(Error::ReadOnly)));
+
+        let registry = MockWindowsRegistry::with_value(REGISTRY_PATH, REGISTRY_KEY, "not-a-uuid");
+        let result = RegistryStorage::with_registry(registry).preflight();
+        assert!(matches!(result, Err(Error::BadUuidFormat(_))));
+    }
+
+    #[test]
+    fn test_empty_value_is_unset() {
+        for value in ["", "  "] {
+            let registry = MockWindowsRegistry::with_value(REGISTRY_PATH, REGISTRY_KEY, value);
+            let mut storage = RegistryStorage::with_registry(registry.clone());
+            assert_eq!(storage.retrieve().unwrap(), None);
+            let id = DevDeviceId::get_or_generate_in(&mut storage).unwrap();
+            assert_eq!(
+                registry.value(REGISTRY_PATH, REGISTRY_KEY),
+                Some(id.to_string())
+            );
+        }
+    }
+
+    /// A key of its own under `HKEY_CURRENT_USER`, outside the device ID key so that a leftover is not
+    /// mistaken for an application, removed when dropped.
+    struct TestKey(String);
+
+    impl TestKey {
+        fn new() -> Self {
+            Self(format!(
+                r"SOFTWARE\deviceid-tests\{}",
+                DevDeviceId::generate()
+            ))
+        }
+    }
+
+    impl Drop for TestKey {
+        fn drop(&mut self) {
+            // best-effort, like the fixture directory
+            let _ = CURRENT_USER.remove_tree(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_live_registry_bounded_read() {
+        let test_key = TestKey::new();
+        let key_path = &test_key.0;
+        let key = CURRENT_USER.create(key_path).unwrap();
+        let id = DevDeviceId::generate();
+        key.set_string(REGISTRY_KEY, id.to_string()).unwrap();
+        key.set_string("oversized", "0".repeat(1 << 20)).unwrap();
+        key.set_u32("number", 42).unwrap();
+
+        let registry = LiveRegistry::default();
+        assert_eq!(
+            registry.get_string(key_path, REGISTRY_KEY).unwrap(),
+            Some(id.to_string())
+        );
+        assert_eq!(registry.get_string(key_path, "missing").unwrap(), None);
+        for name in ["oversized", "number"] {
+            let result = registry.get_string(key_path, name);
+            assert!(
+                matches!(&result, Err(Error::BadUuidFormat(msg)) if msg.contains(name)),
+                "{result:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_scan_locations_finds_conflicting_ids() {
+        let registry = MockWindowsRegistry::new();
+        let user = DevDeviceId::generate();
+        let machine_32bit = DevDeviceId::generate();
+        let mut seed = registry.clone();
+        seed.set_string(REGISTRY_PATH, REGISTRY_KEY, &user.to_string())
+            .unwrap();
+        seed.set_scope(Scope::Machine);
+        seed.set_view_32bit(true);
+        seed.set_string(REGISTRY_PATH, REGISTRY_KEY, &machine_32bit.to_string())
+            .unwrap();
+        seed.set_scope(Scope::User);
+        seed.set_string(REGISTRY_PATH, REGISTRY_KEY, "not-a-uuid")
+            .unwrap();
+
+        let locations = scan_registry(registry.clone());
+        let keys: Vec<_> = locations
+            .iter()
+            .map(|(location, _)| location.display(false))
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                r"HKEY_CURRENT_USER\SOFTWARE\Microsoft\DeveloperTools\deviceid",
+                r"HKEY_CURRENT_USER\SOFTWARE\Microsoft\DeveloperTools\deviceid (32-bit view)",
+                r"HKEY_LOCAL_MACHINE\SOFTWARE\Microsoft\DeveloperTools\deviceid",
+                r"HKEY_LOCAL_MACHINE\SOFTWARE\Microsoft\DeveloperTools\deviceid (32-bit view)",
+            ]
+        );
+        assert_eq!(locations[0].1.as_ref().unwrap(), &Some(user.clone()));
+        // a corrupt location is reported without hiding the others
+        assert!(matches!(locations[1].1, Err(Error::BadUuidFormat(_))));
+        assert_eq!(locations[2].1.as_ref().unwrap(), &None);
+        assert_eq!(locations[3].1.as_ref().unwrap(), &Some(machine_32bit));
+        // scanning does not modify anything
+        assert_eq!(
+            registry.value(REGISTRY_PATH, REGISTRY_KEY),
+            Some(user.to_string())
+        );
+    }
 }
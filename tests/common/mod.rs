@@ -0,0 +1,98 @@
+//! Behavioral suite shared by every storage backend, so that all platforms have identical semantics.
+//!
+//! Each test entry point wires a fresh, empty backend into [`storage_suite!`].
+use deviceid::{DevDeviceId, Error, Storage};
+
+pub fn first_time_generation(mut storage: impl Storage) {
+    assert!(DevDeviceId::get_in(&mut storage).unwrap().is_none());
+
+    let id = DevDeviceId::get_or_generate_in(&mut storage).unwrap();
+    let id2 = DevDeviceId::get_in(&mut storage).unwrap().unwrap();
+    assert_eq!(id, id2);
+}
+
+pub fn idempotency(mut storage: impl Storage) {
+    let id = DevDeviceId::get_or_generate_in(&mut storage).unwrap();
+    let id2 = DevDeviceId::get_or_generate_in(&mut storage).unwrap();
+    assert_eq!(id, id2);
+}
+
+pub fn keeps_existing_id(mut storage: impl Storage) {
+    let id = DevDeviceId::generate();
+    storage.store(&id).unwrap();
+
+    assert_eq!(DevDeviceId::get_or_generate_in(&mut storage).unwrap(), id);
+}
+
+pub fn already_set_conflict(mut storage: impl Storage) {
+    let id = DevDeviceId::generate();
+    storage.store(&id).unwrap();
+
+    let result = storage.store(&DevDeviceId::generate());
+    assert!(matches!(result, Err(Error::AlreadySet)));
+    assert_eq!(storage.retrieve().unwrap(), Some(id));
+}
+
+pub fn overwrite_replaces(mut storage: impl Storage) {
+    storage.store(&DevDeviceId::generate()).unwrap();
+
+    let id = DevDeviceId::generate();
+    storage.overwrite(&id).unwrap();
+    assert_eq!(storage.retrieve().unwrap(), Some(id));
+}
+
+pub fn clear_when_present(mut storage: impl Storage) {
+    let id = DevDeviceId::get_or_generate_in(&mut storage).unwrap();
+    storage.clear().unwrap();
+    assert_eq!(storage.retrieve().unwrap(), None);
+
+    // a cleared storage accepts a new ID
+    let id2 = DevDeviceId::get_or_generate_in(&mut storage).unwrap();
+    assert_ne!(id, id2);
+}
+
+pub fn clear_when_absent(mut storage: impl Storage) {
+    storage.clear().unwrap();
+    storage.clear().unwrap();
+    assert_eq!(storage.retrieve().unwrap(), None);
+}
+
+/// Expands to one `#[test]` per behavioral case, each run against the storage produced by `$fresh`.
+macro_rules! storage_suite {
+    ($fresh:expr) => {
+        #[test]
+        fn suite_first_time_generation() {
+            common::first_time_generation($fresh);
+        }
+
+        #[test]
+        fn suite_idempotency() {
+            common::idempotency($fresh);
+        }
+
+        #[test]
+        fn suite_keeps_existing_id() {
+            common::keeps_existing_id($fresh);
+        }
+
+        #[test]
+        fn suite_already_set_conflict() {
+            common::already_set_conflict($fresh);
+        }
+
+        #[test]
+        fn suite_overwrite_replaces() {
+            common::overwrite_replaces($fresh);
+        }
+
+        #[test]
+        fn suite_clear_when_present() {
+            common::clear_when_present($fresh);
+        }
+
+        #[test]
+        fn suite_clear_when_absent() {
+            common::clear_when_absent($fresh);
+        }
+    };
+}
@@ -0,0 +1,47 @@
+// the ID file is under `XDG_CACHE_HOME` only on Linux
+#![cfg(target_os = "linux")]
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The example binary, built by `cargo test` next to the test binaries.
+fn example() -> PathBuf {
+    let deps = std::env::current_exe().unwrap();
+    deps.parent()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .join("examples")
+        .join("devDeviceId")
+}
+
+fn run(cache_home: &Path, args: &[&str]) -> i32 {
+    Command::new(example())
+        .args(args)
+        .env("XDG_CACHE_HOME", cache_home)
+        .env("XDG_STATE_HOME", cache_home)
+        .env_remove(deviceid::EPHEMERAL_ENV_VAR)
+        .output()
+        .unwrap()
+        .status
+        .code()
+        .unwrap()
+}
+
+#[test]
+fn test_example_exit_codes() {
+    let fixture = deviceid::Fixture::new();
+    let cache_home = fixture.dir();
+
+    assert_eq!(run(cache_home, &["--help"]), 0);
+    assert_eq!(run(cache_home, &["--bogus"]), 2);
+    assert_eq!(run(cache_home, &[]), 1, "no ID is stored yet");
+    assert_eq!(run(cache_home, &["-f"]), 0);
+    assert_eq!(run(cache_home, &[]), 0);
+
+    let file = cache_home
+        .join(deviceid::UNIX_SUBPATH)
+        .join(deviceid::STORAGE_KEY);
+    std::fs::write(&file, "not-a-uuid").unwrap();
+    assert_eq!(run(cache_home, &[]), 4, "the stored ID is corrupt");
+}
@@ -0,0 +1,15 @@
+#[macro_use]
+mod common;
+
+use deviceid::{DevDeviceId, FileStorage, Fixture};
+
+// the fixture lives until the end of each suite call
+storage_suite!(FileStorage::at(Fixture::new().dir().join("deviceid")));
+
+#[test]
+fn test_get_or_generate_at() {
+    let fixture = Fixture::new();
+    let path = fixture.dir().join("deviceid");
+    let id = DevDeviceId::get_or_generate_at(&path).unwrap();
+    assert_eq!(DevDeviceId::get_at(&path).unwrap(), Some(id));
+}
@@ -0,0 +1,6 @@
+#[macro_use]
+mod common;
+
+use deviceid::MemoryStorage;
+
+storage_suite!(MemoryStorage::new());
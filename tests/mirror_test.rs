@@ -0,0 +1,136 @@
+#[macro_use]
+mod common;
+
+use deviceid::{
+    DevDeviceId, Error, FileStorage, Fixture, MemoryStorage, MirrorStorage, Result, Storage,
+};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Mirrors files in the directory of `fixture`, returning the primary and mirror paths too.
+fn file_mirror(fixture: &Fixture) -> (MirrorStorage<FileStorage, FileStorage>, PathBuf, PathBuf) {
+    let dir = fixture.dir();
+    let (primary, mirror) = (dir.join("state/deviceid"), dir.join("config/.deviceid"));
+    let storage = MirrorStorage::new(FileStorage::at(&primary), FileStorage::at(&mirror));
+    (storage, primary, mirror)
+}
+
+storage_suite!(MirrorStorage::new(
+    MemoryStorage::new(),
+    MemoryStorage::new()
+));
+
+mod files {
+    use super::*;
+
+    // the fixture lives until the end of each suite call
+    storage_suite!(file_mirror(&Fixture::new()).0);
+}
+
+#[test]
+fn test_store_writes_both() {
+    let fixture = Fixture::new();
+    let (mut storage, primary, mirror) = file_mirror(&fixture);
+    let id = DevDeviceId::get_or_generate_in(&mut storage).unwrap();
+    assert_eq!(std::fs::read_to_string(primary).unwrap(), id.to_string());
+    assert_eq!(std::fs::read_to_string(mirror).unwrap(), id.to_string());
+}
+
+#[test]
+fn test_wiped_primary_is_restored() {
+    let fixture = Fixture::new();
+    let (mut storage, primary, mirror) = file_mirror(&fixture);
+    let id = DevDeviceId::get_or_generate_in(&mut storage).unwrap();
+    std::fs::remove_file(&primary).unwrap();
+
+    assert_eq!(DevDeviceId::get_or_generate_in(&mut storage).unwrap(), id);
+    assert_eq!(std::fs::read_to_string(primary).unwrap(), id.to_string());
+    assert_eq!(std::fs::read_to_string(mirror).unwrap(), id.to_string());
+}
+
+#[test]
+fn test_wiped_mirror_is_restored() {
+    let fixture = Fixture::new();
+    let (mut storage, _, mirror) = file_mirror(&fixture);
+    let id = DevDeviceId::get_or_generate_in(&mut storage).unwrap();
+    std::fs::remove_dir_all(mirror.parent().unwrap()).unwrap();
+
+    assert_eq!(storage.retrieve().unwrap(), Some(id.clone()));
+    assert_eq!(std::fs::read_to_string(mirror).unwrap(), id.to_string());
+}
+
+#[test]
+fn test_corrupt_primary_falls_back_to_mirror() {
+    let fixture = Fixture::new();
+    let (mut storage, primary, _) = file_mirror(&fixture);
+    let id = DevDeviceId::get_or_generate_in(&mut storage).unwrap();
+    std::fs::write(&primary, "not-a-uuid").unwrap();
+    assert_eq!(storage.retrieve().unwrap(), Some(id.clone()));
+    assert_eq!(std::fs::read_to_string(primary).unwrap(), id.to_string());
+}
+
+/// Holds an ID, but reading it times out.
+#[derive(Default)]
+struct TimingOutStorage {
+    inner: MemoryStorage,
+}
+
+impl Storage for TimingOutStorage {
+    fn retrieve(&mut self) -> Result<Option<DevDeviceId>> {
+        Err(Error::Timeout(Duration::from_secs(1)))
+    }
+
+    fn store(&mut self, id: &DevDeviceId) -> Result<()> {
+        self.inner.store(id)
+    }
+
+    fn overwrite(&mut self, id: &DevDeviceId) -> Result<()> {
+        self.inner.overwrite(id)
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        self.inner.clear()
+    }
+}
+
+#[test]
+fn test_failing_primary_is_not_overwritten() {
+    let mut storage = MirrorStorage::new(TimingOutStorage::default(), MemoryStorage::new());
+    let (id, mirrored) = (DevDeviceId::generate(), DevDeviceId::generate());
+    storage.primary_mut().inner.store(&id).unwrap();
+    storage.mirror_mut().store(&mirrored).unwrap();
+
+    assert_eq!(storage.retrieve().unwrap(), Some(mirrored));
+    assert_eq!(storage.primary_mut().inner.retrieve().unwrap(), Some(id));
+}
+
+#[test]
+fn test_corrupt_mirror_is_repaired() {
+    let fixture = Fixture::new();
+    let (mut storage, _, mirror) = file_mirror(&fixture);
+    let id = DevDeviceId::get_or_generate_in(&mut storage).unwrap();
+    std::fs::write(&mirror, "not-a-uuid").unwrap();
+
+    assert_eq!(storage.retrieve().unwrap(), Some(id.clone()));
+    assert_eq!(std::fs::read_to_string(mirror).unwrap(), id.to_string());
+}
+
+#[test]
+fn test_divergent_mirror_follows_primary() {
+    let fixture = Fixture::new();
+    let (mut storage, _, mirror) = file_mirror(&fixture);
+    let id = DevDeviceId::get_or_generate_in(&mut storage).unwrap();
+    std::fs::write(&mirror, DevDeviceId::generate().to_string()).unwrap();
+
+    assert_eq!(storage.retrieve().unwrap(), Some(id.clone()));
+    assert_eq!(std::fs::read_to_string(mirror).unwrap(), id.to_string());
+}
+
+#[test]
+fn test_clear_removes_both() {
+    let mut storage = MirrorStorage::new(MemoryStorage::new(), MemoryStorage::new());
+    DevDeviceId::get_or_generate_in(&mut storage).unwrap();
+    storage.clear().unwrap();
+    assert_eq!(storage.primary_mut().retrieve().unwrap(), None);
+    assert_eq!(storage.mirror_mut().retrieve().unwrap(), None);
+}
@@ -0,0 +1,80 @@
+#![cfg(all(
+    feature = "uniffi",
+    any(target_family = "unix", target_family = "windows")
+))]
+
+//! Drives `DeviceIdService` through the exported C ABI functions that generated Kotlin and Swift bindings
+//! call, declared by hand here: bindings are generated from a wrapper `cdylib`, see `DeviceIdService`.
+
+use deviceid::{DevDeviceId, Fixture, UniFfiTag};
+use std::ffi::c_void;
+use uniffi::{Lift, Lower, RustBuffer, RustCallStatus, RustCallStatusCode};
+
+unsafe extern "C" {
+    fn uniffi_deviceid_fn_constructor_deviceidservice_new(
+        storage_path: RustBuffer,
+        status: &mut RustCallStatus,
+    ) -> *const c_void;
+    fn uniffi_deviceid_fn_method_deviceidservice_get_or_generate(
+        service: *const c_void,
+        status: &mut RustCallStatus,
+    ) -> RustBuffer;
+    fn uniffi_deviceid_fn_method_deviceidservice_get(
+        service: *const c_void,
+        status: &mut RustCallStatus,
+    ) -> RustBuffer;
+    fn uniffi_deviceid_fn_method_deviceidservice_reset(
+        service: *const c_void,
+        status: &mut RustCallStatus,
+    );
+    fn uniffi_deviceid_fn_clone_deviceidservice(
+        service: *const c_void,
+        status: &mut RustCallStatus,
+    ) -> *const c_void;
+    fn uniffi_deviceid_fn_free_deviceidservice(service: *const c_void, status: &mut RustCallStatus);
+}
+
+/// Runs an FFI call, asserting it succeeded as the bindings check after every call.
+fn call<R>(f: impl FnOnce(&mut RustCallStatus) -> R) -> R {
+    let mut status = RustCallStatus::default();
+    let result = f(&mut status);
+    assert!(matches!(status.code, RustCallStatusCode::Success));
+    result
+}
+
+#[test]
+fn test_service_through_c_abi() {
+    let fixture = Fixture::new();
+    let path = fixture.dir().join("deviceid").to_str().unwrap().to_string();
+    let storage_path = <Option<String> as Lower<UniFfiTag>>::lower(Some(path));
+
+    // SAFETY: each function is called with the argument types it is generated with; like the bindings,
+    // each method call consumes a clone of the service pointer, which is freed last
+    unsafe {
+        let service =
+            call(|status| uniffi_deviceid_fn_constructor_deviceidservice_new(storage_path, status));
+        let get = |service| {
+            let buffer = call(|status| {
+                let service = uniffi_deviceid_fn_clone_deviceidservice(service, status);
+                uniffi_deviceid_fn_method_deviceidservice_get(service, status)
+            });
+            <Option<String> as Lift<UniFfiTag>>::try_lift(buffer).unwrap()
+        };
+        assert_eq!(get(service), None);
+
+        let buffer = call(|status| {
+            let service = uniffi_deviceid_fn_clone_deviceidservice(service, status);
+            uniffi_deviceid_fn_method_deviceidservice_get_or_generate(service, status)
+        });
+        let id = <String as Lift<UniFfiTag>>::try_lift(buffer).unwrap();
+        assert_eq!(id.parse::<DevDeviceId>().unwrap().to_string(), id);
+        assert_eq!(get(service), Some(id));
+
+        call(|status| {
+            let service = uniffi_deviceid_fn_clone_deviceidservice(service, status);
+            uniffi_deviceid_fn_method_deviceidservice_reset(service, status)
+        });
+        assert_eq!(get(service), None);
+        call(|status| uniffi_deviceid_fn_free_deviceidservice(service, status));
+    }
+}
@@ -1,22 +1,21 @@
 #![cfg(unix)]
-use deviceid::DevDeviceId;
-use std::path::PathBuf;
+#[macro_use]
+mod common;
+
+use deviceid::{DevDeviceId, Fixture};
+
+// the fixture lives until the end of each suite call
+storage_suite!(Fixture::new().storage());
 
 #[test]
 fn test_get_or_generate_first_time() {
-    // set HOME to a temporary directory
-    let tmp_home = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("fake_home");
-    if tmp_home.exists() {
-        let _ = std::fs::remove_dir_all(&tmp_home);
-    }
-    println!("Using tmp home: {}", tmp_home.display());
-    std::fs::create_dir_all(&tmp_home).unwrap();
-    unsafe { std::env::set_var("HOME", tmp_home) }
+    let fixture = Fixture::new();
+    let mut storage = fixture.storage();
 
-    let no_id = DevDeviceId::get().unwrap();
+    let no_id = DevDeviceId::get_in(&mut storage).unwrap();
     assert!(no_id.is_none());
 
-    let id = DevDeviceId::get_or_generate().unwrap();
-    let id2 = DevDeviceId::get().unwrap().unwrap();
+    let id = DevDeviceId::get_or_generate_in(&mut storage).unwrap();
+    let id2 = DevDeviceId::get_in(&mut storage).unwrap().unwrap();
     assert_eq!(id, id2);
 }
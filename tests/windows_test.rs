@@ -0,0 +1,15 @@
+#![cfg(windows)]
+#[macro_use]
+mod common;
+
+use deviceid::{DevDeviceId, MockWindowsRegistry, RegistryStorage, Storage};
+
+storage_suite!(RegistryStorage::with_registry(MockWindowsRegistry::new()));
+
+#[test]
+fn test_seeded_registry() {
+    let id = DevDeviceId::generate();
+    let mut storage = RegistryStorage::with_registry(MockWindowsRegistry::seeded(&id));
+    assert_eq!(storage.retrieve().unwrap(), Some(id.clone()));
+    assert_eq!(DevDeviceId::get_or_generate_in(&mut storage).unwrap(), id);
+}